@@ -16,10 +16,12 @@ run = False
 gdb = False
 # Wether the file test.txt should be added to the file system.
 add_file = False
+# Wether to build the tests instead of the kernel, and run them in qemu.
+test = False
 
 def print_usage():
     print("usage: python build.py [options...]")
-    print("  available options: release, run, gdb, help")
+    print("  available options: release, run, gdb, add-file, test, help")
 used_options = []
 for arg in sys.argv[1:]:
     if arg == 'help':
@@ -39,6 +41,10 @@ for arg in sys.argv[1:]:
         print("     add-file")
         print("         Adds the file 'test.txt' to the file system.")
         print("         Currently doesn't work on Windows.")
+        print("     test")
+        print("         Builds the kernel with 'cargo test' and runs the tests in qemu.")
+        print("         The results are printed to the terminal through the serial port.")
+        print("         Exits with a non-zero code if any test failed.")
         print("     help")
         print("         Prints this help screen, and then exits.")
         exit(0)
@@ -66,6 +72,14 @@ for arg in sys.argv[1:]:
             print("Error: Option 'add-file' specified twice")
             print_usage()
             exit(1)
+    elif arg == 'test':
+        if 'test' not in used_options:
+            test = True
+            used_options.append('test')
+        else:
+            print("Error: Option 'test' specified twice")
+            print_usage()
+            exit(1)
     elif arg == 'gdb':
         if 'gdb' not in used_options:
             gdb = True
@@ -84,6 +98,11 @@ if gdb and not run:
     print("       'gdb' must always be used in conjunction with 'run'.")
     exit(1)
 
+if test and run:
+    print("Error: Options 'test' and 'run' specified together")
+    print("       'test' always runs the tests in qemu.")
+    exit(1)
+
 # We need to parse the project metadata to find the local path of the 'bootloader' dependency.
 # For this command to succeed, this script needs to have been called from
 # the project root directory.
@@ -138,12 +157,25 @@ if not os.path.exists(out_path):
     os.mkdir(out_path)
 
 # First, build the project to a normal binary
-build_command = ['cargo', 'build']
-if not debug:
-    build_command.append('--release')
-result = subprocess.run(build_command)
-if result.returncode != 0:
-    exit(1)
+if test:
+    # The test binary gets a hashed name, so we ask cargo where it put it.
+    build_command = ['cargo', 'test', '--no-run', '--message-format=json']
+    if not debug:
+        build_command.append('--release')
+    result = subprocess.run(build_command, stdout=subprocess.PIPE)
+    if result.returncode != 0:
+        exit(1)
+    for line in result.stdout.decode("utf-8").splitlines():
+        message = json.loads(line)
+        if message['reason'] == 'compiler-artifact' and message['profile']['test'] and message['executable'] != None:
+            binary_path = message['executable']
+else:
+    build_command = ['cargo', 'build']
+    if not debug:
+        build_command.append('--release')
+    result = subprocess.run(build_command)
+    if result.returncode != 0:
+        exit(1)
     
 
 # Second, create the bootable disk image
@@ -168,7 +200,27 @@ if add_file:
     subprocess.run(['./insert-file.sh'])
 
 if run:
-    run_command = ['qemu-system-x86_64', '-bios', 'bios.bin', 'out/boot-uefi-brynolv-evper-ofjall-project.img']
+    # '-serial stdio' shows the kernel's serial output in the terminal
+    run_command = ['qemu-system-x86_64', '-bios', 'bios.bin', 'out/boot-uefi-brynolv-evper-ofjall-project.img', '-serial', 'stdio']
     if gdb:
         run_command += ['-s', '-S']
     subprocess.run(run_command)
+
+if test:
+    # The image is named after the binary
+    image_name = 'boot-uefi-' + os.path.basename(binary_path) + '.img'
+    test_command = [
+        'qemu-system-x86_64', '-bios', 'bios.bin', os.path.join('out', image_name),
+        '-device', 'isa-debug-exit,iobase=0xf4,iosize=0x04',
+        '-serial', 'stdio',
+        '-display', 'none'
+    ]
+    result = subprocess.run(test_command)
+    # QEMU exits with (code << 1) | 1, where code is written to the isa-debug-exit port.
+    # See `QemuExitCode` in src/test.rs.
+    if result.returncode == (0x10 << 1) | 1:
+        print("All tests passed")
+        exit(0)
+    else:
+        print("Tests failed")
+        exit(1)
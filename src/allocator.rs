@@ -26,22 +26,50 @@
 //! all alignment requirements. As such, it is possible to retreive a [MemoryBlock] node
 //! from the pointer to the allocated memory.
 //!
-//! The heap starts at the address `0xFFFFF00000000000`.
+//! By default, the heap starts at the address `0xFFFFF00000000000` and may grow
+//! to at most [DEFAULT_HEAP_SIZE] bytes. Both can be configured with [initialize_with_heap].
+//! Allocations which would grow the heap past its end fail by returning a null pointer.
 //!
 //! Using one [MemoryBlock] for every allocation is not optimal; every allocation will get some overhead. Many small allocations
 //! will use much more memory than a few large ones.
 //!
+//! # Leak detection
+//!
+//! In debug builds, every [MemoryBlock] also records the allocation tag that was
+//! current when it was allocated (see [set_allocation_tag]).
+//! [dump_live_allocations] walks the linked list and prints every live allocation,
+//! which makes it possible to find allocations that are never freed.
+//! Release builds do not store any tags.
+//!
 //! # Virtual memory mapping
 //!
 //! Virtual memory mapping is handled by the [MEMORY_MAPPER] static.
 //! Mapping an address may allocate additional physical frames as needed.
+//! The [MemoryAllocator] only maps and unmaps pages through [HeapPages], which for
+//! [MEMORY_ALLOCATOR] is [KernelPages], using [MEMORY_MAPPER] and [FRAME_ALLOCATOR].
+//!
+//! # Locking
+//!
+//! [FRAME_ALLOCATOR], [MEMORY_MAPPER] and [MEMORY_ALLOCATOR] share a single lock, [MEMORY_LOCK],
+//! which is only taken through [with_memory_lock]. Allocating memory uses all three, so one lock
+//! for them all means there is no order between locks to get wrong. Any code touching them
+//! within the lock must still do so in the order frame allocator → memory mapper → memory allocator
+//! (allocating a frame before mapping it, and mapping a page before handing it out), so that an
+//! unfinished operation never leaves a page mapped to a frame which is marked unused.
+//!
+//! Interrupts are disabled while the lock is held, so an interrupt handler which allocates
+//! simply waits until the allocation it interrupted is done. Exceptions, such as page faults,
+//! can't be disabled; if one happens inside the lock and its handler tries to allocate,
+//! [with_memory_lock] panics instead of letting the handler corrupt the page tables or the bitmap.
 
 use core::{
 	alloc::{GlobalAlloc, Layout},
+	convert::TryInto,
 	ptr::NonNull,
 };
 
 use bootloader::boot_info::{MemoryRegion, MemoryRegionKind};
+use spin::Mutex;
 use x86_64::{
 	addr,
 	registers::control::Cr3,
@@ -54,6 +82,14 @@ use x86_64::{
 /// Currently, this is 32GiB.
 const MAX_PHYS_MEM: usize = 32 * 1024 * 1024 * 1024 >> 12;
 
+/// The default start of the heap.
+pub const DEFAULT_HEAP_START: u64 = 0xFFFF_F000_0000_0000;
+
+/// The default maximum size of the heap, in bytes.
+///
+/// Currently, this is the same as the maximum supported physical memory.
+pub const DEFAULT_HEAP_SIZE: u64 = (MAX_PHYS_MEM as u64) << 12;
+
 /// Handles physical memory allocation.
 ///
 /// Every frame starts out as unusable.
@@ -75,8 +111,32 @@ static mut MEMORY_MAPPER: MemoryMapper = MemoryMapper { pml4t_ptr: 0 as _ };
 #[global_allocator]
 static mut MEMORY_ALLOCATOR: MemoryAllocator = MemoryAllocator {
 	first_block: 0 as _,
+	heap_end: 0,
+	pages: KernelPages,
 };
 
+/// Held while [FRAME_ALLOCATOR], [MEMORY_MAPPER] or [MEMORY_ALLOCATOR] is used.
+///
+/// See [with_memory_lock], and the module documentation.
+static MEMORY_LOCK: Mutex<()> = Mutex::new(());
+
+/// Runs `f` with interrupts disabled and [MEMORY_LOCK] held.
+///
+/// # Panics
+///
+/// Panics if the lock is already held. As interrupts are disabled while it is held,
+/// that only happens if an exception handler tries to use the memory allocators
+/// while they are in the middle of something, which would otherwise corrupt them.
+/// Waiting for the lock would hang forever, as the holder can't continue until the handler returns.
+fn with_memory_lock<R>(f: impl FnOnce() -> R) -> R {
+	x86_64::instructions::interrupts::without_interrupts(|| {
+		let _guard = MEMORY_LOCK
+			.try_lock()
+			.expect("The memory allocators were used reentrantly");
+		f()
+	})
+}
+
 /// A physical frame allocator.
 ///
 /// Contains a simple bitmap which is used to keep track of allocated/freed
@@ -122,18 +182,14 @@ impl FrameAllocator {
 	/// # Panics
 	///
 	/// Panics if `index` is above or equal to [MAX_PHYS_MEM].
-	/// May also panic if the frame index given is the last available frame.
 	fn set_used(&mut self, index: usize) {
 		let b = index / 8;
 		let r = index % 8;
 		self.free_frames[b] |= 1 << r;
 		if index == self.first_free_frame {
-			for _ in self.first_free_frame..=self.last_free_frame {
-				self.first_free_frame += 1;
-				if !self.get(self.first_free_frame) {
-					break;
-				}
-			}
+			self.first_free_frame = self
+				.find_unused(index + 1, self.last_free_frame)
+				.unwrap_or(self.last_free_frame + 1);
 		}
 		if index == self.last_free_frame {
 			for _ in self.first_free_frame..=self.last_free_frame {
@@ -176,6 +232,42 @@ impl FrameAllocator {
 		self.free_frames[index / 8] & 1 << index % 8 > 0
 	}
 
+	/// Finds the first unused frame in `start..=end`.
+	///
+	/// Words of 64 frames which are all used are skipped at once, and then bytes of 8 frames,
+	/// so only the word or byte containing the unused frame has to be searched bit by bit.
+	///
+	/// # Panics
+	///
+	/// Panics if `end` is above or equal to [MAX_PHYS_MEM].
+	fn find_unused(&self, start: usize, end: usize) -> Option<usize> {
+		let mut index = start;
+		while index <= end {
+			if index % 64 == 0 && index + 63 <= end {
+				let b = index / 8;
+				let word = u64::from_le_bytes(self.free_frames[b..b + 8].try_into().unwrap());
+				if word == u64::MAX {
+					index += 64;
+					continue;
+				}
+				return Some(index + (!word).trailing_zeros() as usize);
+			}
+			if index % 8 == 0 && index + 7 <= end {
+				let byte = self.free_frames[index / 8];
+				if byte == 0xFF {
+					index += 8;
+					continue;
+				}
+				return Some(index + (!byte).trailing_zeros() as usize);
+			}
+			if !self.get(index) {
+				return Some(index);
+			}
+			index += 1;
+		}
+		None
+	}
+
 	/// Allocates a physical frame.
 	///
 	/// If there are no free frames available,
@@ -195,6 +287,34 @@ impl FrameAllocator {
 	fn free_frame(&mut self, frame: PhysFrame) {
 		self.set_unused(frame.start_address().as_u64() as usize >> 12);
 	}
+
+	/// Allocates `count` physically consecutive frames,
+	/// where the index of the first frame is a multiple of `align`.
+	///
+	/// Returns the first frame, or `None` if there are not enough consecutive free frames.
+	///
+	/// # Panics
+	///
+	/// Panics if `count` or `align` is 0.
+	fn allocate_contiguous(&mut self, count: usize, align: usize) -> Option<PhysFrame> {
+		assert!(count > 0 && align > 0);
+		let mut start = align_up(self.first_free_frame as u64, align as u64) as usize;
+		'search: while start + count - 1 <= self.last_free_frame {
+			for index in start..start + count {
+				if self.get(index) {
+					// The range can't contain this frame, so continue at the next unused one
+					let next_unused = self.find_unused(index + 1, self.last_free_frame)?;
+					start = align_up(next_unused as u64, align as u64) as usize;
+					continue 'search;
+				}
+			}
+			for index in start..start + count {
+				self.set_used(index);
+			}
+			return Some(PhysFrame::from_start_address(PhysAddr::new((start as u64) << 12)).unwrap());
+		}
+		None
+	}
 }
 
 /// A virtual to physical memory mapper.
@@ -399,6 +519,41 @@ fn get_page_table_indices(virt: VirtAddr) -> (usize, usize, usize, usize) {
 	(idx4, idx3, idx2, idx1)
 }
 
+/// Maps the pages a [MemoryAllocator] grows its heap into, and unmaps those it no longer uses.
+trait HeapPages {
+	/// Maps the page at `virt` to a new frame, if it isn't already mapped.
+	///
+	/// # Safety
+	///
+	/// The page must not contain used memory, unless it is already mapped.
+	unsafe fn map_page(&self, virt: VirtAddr);
+
+	/// Unmaps the page at `virt`, and frees its frame.
+	///
+	/// # Safety
+	///
+	/// See [`MemoryMapper::unmap`].
+	unsafe fn unmap_page(&self, virt: VirtAddr);
+}
+
+/// The pages of the kernel's page table, mapped by [MEMORY_MAPPER] to frames from [FRAME_ALLOCATOR].
+///
+/// Both must have been initialized before any page is mapped or unmapped.
+struct KernelPages;
+
+impl HeapPages for KernelPages {
+	unsafe fn map_page(&self, virt: VirtAddr) {
+		if !MEMORY_MAPPER.is_mapped(virt) {
+			let frame = FRAME_ALLOCATOR.allocate_frame();
+			MEMORY_MAPPER.map(virt, frame);
+		}
+	}
+
+	unsafe fn unmap_page(&self, virt: VirtAddr) {
+		MEMORY_MAPPER.unmap(virt);
+	}
+}
+
 /// A virtual memory allocator.
 ///
 /// A linked list is used to keep track of allocated memory.
@@ -407,29 +562,42 @@ fn get_page_table_indices(virt: VirtAddr) -> (usize, usize, usize, usize) {
 /// while still following it's alignment requirement.
 /// This allows the allocator to get an allocated memory's [MemoryBlock]
 /// without much trouble.
-struct MemoryAllocator {
+///
+/// No allocation will ever extend past [`Self::heap_end`].
+struct MemoryAllocator<P: HeapPages = KernelPages> {
 	first_block: *mut MemoryBlock,
+	/// The address just past the end of the heap.
+	heap_end: u64,
+	/// Maps the pages the heap uses.
+	pages: P,
 }
 
-impl MemoryAllocator {
+impl<P: HeapPages> MemoryAllocator<P> {
 	/// Initializes the [MemoryAllocator] and
-	/// tells it to use `start_addr` as the start of the heap.
+	/// tells it to use `start_addr` as the start of the heap,
+	/// and to never grow the heap past `start_addr + size`.
 	///
 	/// The [MemoryAllocator] sets up an empty [MemoryBlock] at the
 	/// start of the heap, which acts as the first node in the linked list.
 	/// The first [MemoryBlock] will never get deallocated.
 	///
+	/// # Panics
+	///
+	/// Panics if `size` is too small to fit the first [MemoryBlock].
+	///
 	/// # Safety
 	///
 	/// - `FRAME_ALLOCATOR.initialize(..)` must have been called
 	/// - `MEMORY_MAPPER.initialize(..)` must have been called
-	/// - `start_addr` must not point to used memory
-	unsafe fn initialize(&mut self, start_addr: u64) {
-		// Make sure the page at start_addr is mapped.
-		if !MEMORY_MAPPER.is_mapped(VirtAddr::new(start_addr)) {
-			let frame = FRAME_ALLOCATOR.allocate_frame();
-			MEMORY_MAPPER.map(VirtAddr::new(start_addr), frame);
+	/// - The range `start_addr..start_addr + size` must not contain used memory
+	unsafe fn initialize(&mut self, start_addr: u64, size: u64) {
+		if size < core::mem::size_of::<MemoryBlock>() as u64 {
+			panic!("Heap is too small");
 		}
+		self.heap_end = start_addr + size;
+
+		// Make sure the page at start_addr is mapped.
+		self.pages.map_page(VirtAddr::new(start_addr));
 
 		// We need to write a MemoryBlock to the start.
 		// This MemoryBlock will not keep track of any
@@ -446,21 +614,25 @@ impl MemoryAllocator {
 				start_addr + core::mem::size_of::<MemoryBlock>() as u64,
 				core::mem::align_of::<()>() as _,
 			),
+			#[cfg(debug_assertions)]
+			tag: "heap start",
 		});
 	}
 }
 
-unsafe impl GlobalAlloc for MemoryAllocator {
+unsafe impl<P: HeapPages> GlobalAlloc for MemoryAllocator<P> {
 	/// Allocates virtual memory conforming to the given layout.
 	///
+	/// Returns a null pointer if the allocation would grow the heap
+	/// past [`Self::heap_end`].
+	///
 	/// # Safety
 	///
-	/// - There must be enough unused space on the heap
-	/// - See [`GlobalAlloc::alloc`] for more
+	/// See [`GlobalAlloc::alloc`]
 	unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
 		// Make sure we are not interrupted (lol) while we allocate.
 		// We don't want an unexpected interrupt to corrupt the page table!
-		x86_64::instructions::interrupts::without_interrupts(|| {
+		with_memory_lock(|| {
 			let mut current_block = self.first_block;
 
 			// Walk through the linked list
@@ -483,14 +655,31 @@ unsafe impl GlobalAlloc for MemoryAllocator {
 				// then there isn't enough space and we should keep walking the list.
 				// Else, we have found a place for our allocation and can stop here.
 				if next.as_ptr() as u64 > data_after_block_addr + layout.size() as u64 {
-					let new_block = (*current_block).spawn_block(layout, Some(next));
+					let new_block = (*current_block).spawn_block(layout, Some(next), &self.pages);
 					let addr = new_block.as_ref().data as _;
 					return addr;
 				}
 				current_block = next.as_ptr();
 			}
 
-			let addr = (*current_block).spawn_block(layout, None).as_ref().data as _;
+			// The new allocation will be placed last, so we must make sure
+			// that it doesn't grow the heap past its end.
+			let block_addr = align_up(
+				(*current_block).end_of_data_addr(),
+				core::mem::align_of::<MemoryBlock>() as _,
+			);
+			let data_addr = align_up(
+				block_addr + core::mem::size_of::<MemoryBlock>() as u64,
+				layout.align() as _,
+			);
+			if data_addr + layout.size() as u64 > self.heap_end {
+				return core::ptr::null_mut();
+			}
+
+			let addr = (*current_block)
+				.spawn_block(layout, None, &self.pages)
+				.as_ref()
+				.data as _;
 			addr
 		})
 	}
@@ -502,7 +691,7 @@ unsafe impl GlobalAlloc for MemoryAllocator {
 	/// See [`GlobalAlloc::dealloc`]
 	unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
 		// Same logic as in alloc
-		x86_64::instructions::interrupts::without_interrupts(|| {
+		with_memory_lock(|| {
 			// This is the address of the MemoryBlock,
 			// as it's placed as close to the allocation as possible.
 			let block_addr = align_down(
@@ -534,7 +723,7 @@ unsafe impl GlobalAlloc for MemoryAllocator {
 			let min_page_to_unmap = (prev_max_page + 1).max(cur_min_page);
 			let max_page_to_unmap = cur_max_page.min(next_min_page - 1);
 			for page in min_page_to_unmap..=max_page_to_unmap {
-				MEMORY_MAPPER.unmap(VirtAddr::new(page << 12));
+				self.pages.unmap_page(VirtAddr::new(page << 12));
 			}
 
 			// We need to replace the neighbouring nodes next and prev pointers.
@@ -554,6 +743,9 @@ struct MemoryBlock {
 	layout: Layout,
 	/// Address of the allocated memory.
 	data: u64,
+	/// The allocation tag that was current when the memory was allocated.
+	#[cfg(debug_assertions)]
+	tag: &'static str,
 }
 
 impl MemoryBlock {
@@ -562,7 +754,7 @@ impl MemoryBlock {
 		self.data + self.layout.size() as u64
 	}
 
-	/// Creates a new allocation just past this one.
+	/// Creates a new allocation just past this one, mapping its pages with `pages`.
 	///
 	/// # Safety
 	///
@@ -572,6 +764,7 @@ impl MemoryBlock {
 		&mut self,
 		layout: Layout,
 		next: Option<NonNull<MemoryBlock>>,
+		pages: &impl HeapPages,
 	) -> NonNull<MemoryBlock> {
 		// The new MemoryBlock must be past this one's allocated memory,
 		// and it must be correctly aligned.
@@ -597,11 +790,7 @@ impl MemoryBlock {
 		let last_page = data_addr + layout.size() as u64 - 1 >> 12;
 
 		for page in first_page..=last_page {
-			let addr = VirtAddr::new(page << 12);
-			if !MEMORY_MAPPER.is_mapped(addr) {
-				let frame = FRAME_ALLOCATOR.allocate_frame();
-				MEMORY_MAPPER.map(addr, frame);
-			}
+			pages.map_page(VirtAddr::new(page << 12));
 		}
 
 		(block_addr as *mut MemoryBlock).write(MemoryBlock {
@@ -609,6 +798,8 @@ impl MemoryBlock {
 			next,
 			layout,
 			data: data_addr,
+			#[cfg(debug_assertions)]
+			tag: ALLOCATION_TAG,
 		});
 		let ptr = NonNull::new_unchecked(block_addr as _);
 		// Update the previous nodes next pointer,
@@ -625,20 +816,143 @@ impl MemoryBlock {
 
 /// Initializes all components necessary for dynamic memory allocation.
 ///
-/// Uses `0xFFFFF00000000000` as the start of the heap.
+/// Uses [DEFAULT_HEAP_START] as the start of the heap,
+/// and [DEFAULT_HEAP_SIZE] as its maximum size.
+/// See [initialize_with_heap] for more.
+///
+/// # Safety
+///
+/// Must not be called concurrently.
+/// Calling this function multiple times might cause undefined behaviour.
+pub unsafe fn initialize(mem: &[MemoryRegion]) {
+	initialize_with_heap(mem, DEFAULT_HEAP_START, DEFAULT_HEAP_SIZE);
+}
+
+/// Initializes all components necessary for dynamic memory allocation,
+/// using `heap_start` as the start of the heap and never growing it
+/// past `heap_start + heap_size`.
+///
 /// Also marks frame 0 as used, to prevent any data being
 /// allocated at address 0, which would be indistinguishable from
 /// a null (invalid) pointer.
 ///
+/// # Panics
+///
+/// Panics if the heap would overlap the mapping of physical memory.
+///
 /// # Safety
 ///
 /// Must not be called concurrently.
 /// Calling this function multiple times might cause undefined behaviour.
-pub unsafe fn initialize(mem: &[MemoryRegion]) {
-	FRAME_ALLOCATOR.initialize(mem);
-	FRAME_ALLOCATOR.set_used(0);
-	MEMORY_MAPPER.initialize();
-	MEMORY_ALLOCATOR.initialize(0xFFFF_F000_0000_0000);
+/// The range `heap_start..heap_start + heap_size` must not contain used memory.
+pub unsafe fn initialize_with_heap(mem: &[MemoryRegion], heap_start: u64, heap_size: u64) {
+	if heap_start
+		.checked_add(heap_size)
+		.map_or(true, |end| end > PHYS_MAP_START)
+	{
+		panic!("Heap overlaps physical memory mapping");
+	}
+	with_memory_lock(|| {
+		FRAME_ALLOCATOR.initialize(mem);
+		FRAME_ALLOCATOR.set_used(0);
+		MEMORY_MAPPER.initialize();
+		MEMORY_ALLOCATOR.initialize(heap_start, heap_size);
+	})
+}
+
+/// Allocates `count` physically consecutive frames, for use as a DMA buffer.
+///
+/// The physical address of the first frame is aligned to `align` bytes, which must be a multiple of 4KiB.
+/// Returns the virtual and physical address of the buffer, or `None` if there are not enough
+/// consecutive free frames. The virtual address lies in the mapping of physical memory,
+/// so the buffer is contiguous in virtual memory too.
+///
+/// The buffer must be freed with [free_contiguous].
+///
+/// # Panics
+///
+/// Panics if `count` is 0, or if `align` is not a non-zero multiple of 4KiB.
+///
+/// # Safety
+///
+/// [initialize] must have been called.
+pub unsafe fn allocate_contiguous(count: usize, align: u64) -> Option<(VirtAddr, PhysAddr)> {
+	assert!(
+		align > 0 && align % 4096 == 0,
+		"Alignment must be a multiple of 4KiB"
+	);
+	with_memory_lock(|| {
+		let frame = FRAME_ALLOCATOR.allocate_contiguous(count, (align >> 12) as usize)?;
+		Some((phys_to_virt(frame.start_address()), frame.start_address()))
+	})
+}
+
+/// Frees `count` frames starting at `phys`, which were allocated with [allocate_contiguous].
+///
+/// # Safety
+///
+/// The frames must have been allocated with [allocate_contiguous],
+/// and may not be used after this call.
+pub unsafe fn free_contiguous(phys: PhysAddr, count: usize) {
+	with_memory_lock(|| {
+		let first_frame = phys.as_u64() as usize >> 12;
+		for index in first_frame..first_frame + count {
+			FRAME_ALLOCATOR.set_unused(index);
+		}
+	})
+}
+
+/// The tag given to new allocations.
+#[cfg(debug_assertions)]
+static mut ALLOCATION_TAG: &'static str = "untagged";
+
+/// Sets the tag given to all following allocations, and returns the previous tag.
+///
+/// Used to find out where leaked memory was allocated.
+/// Only available in debug builds.
+///
+/// # Example
+///
+/// ```
+/// let old_tag = allocator::set_allocation_tag("fat32");
+/// // Allocations made here are tagged with "fat32"
+/// allocator::set_allocation_tag(old_tag);
+/// ```
+#[cfg(debug_assertions)]
+pub fn set_allocation_tag(tag: &'static str) -> &'static str {
+	x86_64::instructions::interrupts::without_interrupts(|| unsafe {
+		core::mem::replace(&mut ALLOCATION_TAG, tag)
+	})
+}
+
+/// Prints every currently live allocation, together with its size and tag.
+///
+/// Only available in debug builds.
+///
+/// # Safety
+///
+/// [initialize] must have been called.
+#[cfg(debug_assertions)]
+pub unsafe fn dump_live_allocations() {
+	with_memory_lock(|| {
+		let mut count = 0;
+		let mut total_size = 0;
+		// The first block doesn't track any allocated memory, so it is skipped.
+		let mut current_block = (*MEMORY_ALLOCATOR.first_block).next;
+		while let Some(block) = current_block {
+			let block = block.as_ref();
+			println!(
+				"{:#018X}  {:8}  {}",
+				block.data,
+				block.layout.size(),
+				block.tag
+			);
+			count += 1;
+			total_size += block.layout.size();
+			current_block = block.next;
+		}
+		println!("{} live allocations, {} bytes in total", count, total_size);
+	})
 }
 
 /// The address which physical memory has been mapped to.
@@ -659,3 +973,199 @@ fn align_up(addr: u64, align: u64) -> u64 {
 	let rest = addr % align;
 	if rest == 0 { addr } else { addr + align - rest }
 }
+
+#[cfg(test)]
+mod tests {
+	use alloc::vec::Vec;
+	use core::{
+		alloc::{GlobalAlloc, Layout},
+		cell::RefCell,
+		sync::atomic::{AtomicUsize, Ordering},
+	};
+
+	use x86_64::{instructions::port::Port, structures::idt::InterruptStackFrame, VirtAddr};
+
+	use super::{
+		allocate_contiguous, free_contiguous, phys_to_virt, HeapPages, MemoryAllocator,
+		FRAME_ALLOCATOR, MEMORY_LOCK,
+	};
+	use crate::svec::SVec;
+
+	/// How many times [allocating_timer_handler] has run
+	static TIMER_TICKS: AtomicUsize = AtomicUsize::new(0);
+
+	/// Allocates and frees memory on every tick of the PIT
+	extern "x86-interrupt" fn allocating_timer_handler(_: InterruptStackFrame) {
+		let data: Vec<u64> = (0..64).collect();
+		assert!(data.iter().enumerate().all(|(i, &v)| v == i as u64));
+		drop(data);
+		TIMER_TICKS.fetch_add(1, Ordering::Relaxed);
+		unsafe { crate::pic::send_eoi(0) };
+	}
+
+	#[test_case]
+	fn allocating_while_timer_fires() {
+		unsafe {
+			// PIT channel 0, low byte then high byte, mode 2 (rate generator), about 1 kHz
+			let divisor: u16 = 1193;
+			Port::<u8>::new(0x43).write(0b0011_0100);
+			Port::<u8>::new(0x40).write(divisor as u8);
+			Port::<u8>::new(0x40).write((divisor >> 8) as u8);
+			crate::idt::register_irq(0x20, allocating_timer_handler);
+			crate::pic::enable_interrupt(0);
+		}
+
+		let mut round = 0;
+		while TIMER_TICKS.load(Ordering::Relaxed) < 50 {
+			// Different sizes, so blocks are both reused and added at the end of the heap
+			let data: Vec<usize> = (0..round % 700 + 1).collect();
+			assert!(data.iter().enumerate().all(|(i, &v)| v == i));
+			let other: Vec<u8> = Vec::with_capacity(round % 5000 + 1);
+			drop(data);
+			drop(other);
+			round += 1;
+		}
+
+		unsafe { crate::pic::disable_interrupt(0) };
+		assert!(!MEMORY_LOCK.is_locked());
+	}
+
+	/// Records which pages are mapped, instead of mapping them
+	struct RecordingPages {
+		mapped: RefCell<SVec<u64, 8>>,
+	}
+
+	impl HeapPages for RecordingPages {
+		unsafe fn map_page(&self, virt: VirtAddr) {
+			let mut mapped = self.mapped.borrow_mut();
+			if !mapped.contains(&virt.as_u64()) {
+				mapped.push(virt.as_u64());
+			}
+		}
+
+		unsafe fn unmap_page(&self, virt: VirtAddr) {
+			let mut mapped = self.mapped.borrow_mut();
+			let index = mapped
+				.position(|&page| page == virt.as_u64())
+				.expect("Page is not mapped");
+			mapped.remove(index);
+		}
+	}
+
+	/// Memory for [heap_never_grows_past_its_end], which is already mapped as part of the kernel
+	#[repr(align(4096))]
+	struct HeapMemory([u8; 3 * 4096]);
+
+	static mut HEAP_MEMORY: HeapMemory = HeapMemory([0; 3 * 4096]);
+
+	#[test_case]
+	fn heap_never_grows_past_its_end() {
+		unsafe {
+			// Two of the three pages, so a page past the end would still be memory of the test
+			let heap_start = &HEAP_MEMORY as *const _ as u64;
+			let heap_size = 8192;
+			let heap_pages = heap_start..heap_start + heap_size;
+			let mut heap = MemoryAllocator {
+				first_block: core::ptr::null_mut(),
+				heap_end: 0,
+				pages: RecordingPages {
+					mapped: RefCell::new(SVec::new()),
+				},
+			};
+			heap.initialize(heap_start, heap_size);
+			assert_eq!(heap.pages.mapped.borrow().get_slice(), &[heap_start]);
+
+			let first = heap.alloc(Layout::from_size_align(4096, 8).unwrap());
+			assert!(!first.is_null());
+			assert!(first as u64 + 4096 <= heap_start + heap_size);
+
+			// Only about 4 KiB are left
+			let too_big = Layout::from_size_align(4096, 8).unwrap();
+			assert!(heap.alloc(too_big).is_null());
+			let too_aligned = Layout::from_size_align(16, 8192).unwrap();
+			assert!(heap.alloc(too_aligned).is_null());
+
+			let small = heap.alloc(Layout::from_size_align(1024, 8).unwrap());
+			assert!(!small.is_null());
+			assert!(small as u64 + 1024 <= heap_start + heap_size);
+			assert!(heap
+				.pages
+				.mapped
+				.borrow()
+				.get_slice()
+				.iter()
+				.all(|page| heap_pages.contains(page)));
+
+			heap.dealloc(small, Layout::from_size_align(1024, 8).unwrap());
+			heap.dealloc(first, Layout::from_size_align(4096, 8).unwrap());
+			// The page of the first block is never unmapped
+			assert_eq!(heap.pages.mapped.borrow().get_slice(), &[heap_start]);
+		}
+	}
+
+	#[test_case]
+	fn freed_frames_are_reused() {
+		x86_64::instructions::interrupts::without_interrupts(|| unsafe {
+			let a = FRAME_ALLOCATOR.allocate_frame();
+			let b = FRAME_ALLOCATOR.allocate_frame();
+			let c = FRAME_ALLOCATOR.allocate_frame();
+			assert!(a != b && b != c && a != c);
+
+			FRAME_ALLOCATOR.free_frame(b);
+			assert_eq!(FRAME_ALLOCATOR.allocate_frame(), b);
+
+			FRAME_ALLOCATOR.free_frame(c);
+			FRAME_ALLOCATOR.free_frame(a);
+			assert_eq!(FRAME_ALLOCATOR.allocate_frame(), a);
+			assert_eq!(FRAME_ALLOCATOR.allocate_frame(), c);
+
+			FRAME_ALLOCATOR.free_frame(a);
+			FRAME_ALLOCATOR.free_frame(b);
+			FRAME_ALLOCATOR.free_frame(c);
+		})
+	}
+
+	#[test_case]
+	fn unused_frames_are_found_past_used_words() {
+		unsafe {
+			let first_free_frame = FRAME_ALLOCATOR.first_free_frame;
+			// Use 200 frames, so whole words and bytes have to be skipped
+			let (_, phys) = allocate_contiguous(200, 4096).unwrap();
+			let first_frame = phys.as_u64() as usize >> 12;
+			let last_free_frame = FRAME_ALLOCATOR.last_free_frame;
+			let bit_by_bit = (first_frame..=last_free_frame).find(|&i| !FRAME_ALLOCATOR.get(i));
+			assert_eq!(
+				FRAME_ALLOCATOR.find_unused(first_frame, last_free_frame),
+				bit_by_bit
+			);
+			assert!(bit_by_bit.unwrap() >= first_frame + 200);
+			free_contiguous(phys, 200);
+			assert_eq!(FRAME_ALLOCATOR.first_free_frame, first_free_frame);
+		}
+	}
+
+	#[test_case]
+	fn contiguous_frames_are_adjacent() {
+		unsafe {
+			let (virt, phys) = allocate_contiguous(8, 4096 * 4).unwrap();
+			assert_eq!(phys.as_u64() % (4096 * 4), 0);
+			assert_eq!(virt, phys_to_virt(phys));
+			let first_frame = phys.as_u64() as usize >> 12;
+			for index in first_frame..first_frame + 8 {
+				assert!(FRAME_ALLOCATOR.get(index));
+			}
+			// The whole buffer must be usable
+			core::ptr::write_bytes(virt.as_mut_ptr::<u8>(), 0xAB, 8 * 4096);
+
+			let (_, other_phys) = allocate_contiguous(8, 4096).unwrap();
+			let other_first_frame = other_phys.as_u64() as usize >> 12;
+			assert!(other_first_frame + 8 <= first_frame || other_first_frame >= first_frame + 8);
+
+			free_contiguous(other_phys, 8);
+			free_contiguous(phys, 8);
+			for index in first_frame..first_frame + 8 {
+				assert!(!FRAME_ALLOCATOR.get(index));
+			}
+		}
+	}
+}
@@ -1,15 +1,41 @@
-use alloc::{vec, vec::Vec};
+use alloc::{format, string::String, vec, vec::Vec};
 use core::{
 	borrow::BorrowMut,
 	convert::{TryFrom, TryInto},
+	fmt::Display,
 	hint::unreachable_unchecked,
 };
 
-use super::partitions::Partition;
+use super::{partitions::Partition, pata::PataError};
 use crate::svec::SVec;
 
 /// The char used for directory seperation (standard is '/', but we are having fun here)
 const SEPARATOR_CHAR: u8 = b'>';
+/// Also accepted as a separator in input paths, since it's what everyone is used to
+const ALT_SEPARATOR_CHAR: u8 = b'/';
+
+/// The number of FAT sectors read from disk, so tests can tell when the FAT is read needlessly
+#[cfg(test)]
+static FAT_SECTOR_READS: core::sync::atomic::AtomicUsize = core::sync::atomic::AtomicUsize::new(0);
+
+/// The number of sectors written to disk, so tests can compare how much different ways of writing cost
+#[cfg(test)]
+static SECTOR_WRITES: core::sync::atomic::AtomicUsize = core::sync::atomic::AtomicUsize::new(0);
+
+/// Is `b` one of the accepted directory separators?
+fn is_separator(b: u8) -> bool {
+	b == SEPARATOR_CHAR || b == ALT_SEPARATOR_CHAR
+}
+
+/// Splits `path` at its last separator, into the path of the parent directory and the name.
+///
+/// Like [`SplitLast::split_last_2`], returns `(path, [])` if there is no separator.
+fn split_path(path: Path) -> (Path, Path) {
+	match path.iter().rposition(|b| is_separator(*b)) {
+		Some(index) => (&path[..index], &path[index + 1..]),
+		None => (path, &[]),
+	}
+}
 
 #[derive(Clone, Debug)]
 pub struct FileInfo {
@@ -19,55 +45,463 @@ pub struct FileInfo {
 	pub size: usize,
 	/// If the file is, in fact, a directory
 	pub is_directory: bool,
+	/// The attribute bits of the directory entry, see [ATTRIBUTE_READ_ONLY] and the others
+	pub attributes: u8,
+	/// The long file name, if the entry has one.
+	///
+	/// Files can be looked up by either name.
+	pub long_name: Option<String>,
+	/// When the file was created, if that is recorded
+	pub created: Option<FatTimestamp>,
+	/// When the file was last written to, if that is recorded
+	pub modified: Option<FatTimestamp>,
 	first_cluster: u32,
 }
 
-type Path<'a> = &'a [u8];
+/// A date and time as stored in a directory entry, which has a resolution of 2 seconds
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FatTimestamp {
+	/// 1980 to 2107
+	pub year: u16,
+	/// 1 to 12
+	pub month: u8,
+	/// 1 to 31
+	pub day: u8,
+	pub hour: u8,
+	pub minute: u8,
+	/// Always even
+	pub second: u8,
+}
+
+impl FatTimestamp {
+	/// Reads the time and the date after it, starting at `offset` in the directory entry `entry`.
+	///
+	/// Returns `None` if the date is 0, which means it isn't recorded.
+	fn read(entry: &[u8], offset: usize) -> Option<Self> {
+		let time = u16::from_le_bytes([entry[offset], entry[offset + 1]]);
+		let date = u16::from_le_bytes([entry[offset + 2], entry[offset + 3]]);
+		if date == 0 {
+			return None;
+		}
+		Some(Self {
+			year: 1980 + (date >> 9),
+			month: (date >> 5 & 0x0F) as u8,
+			day: (date & 0x1F) as u8,
+			hour: (time >> 11) as u8,
+			minute: (time >> 5 & 0x3F) as u8,
+			second: (time & 0x1F) as u8 * 2,
+		})
+	}
+
+	/// Writes `timestamp` like [`FatTimestamp::read`] reads it, or zeroes if it is `None`
+	fn write(timestamp: Option<Self>, entry: &mut [u8], offset: usize) {
+		let (time, date) = match timestamp {
+			Some(t) => (
+				(t.hour as u16) << 11 | (t.minute as u16) << 5 | (t.second / 2) as u16,
+				(t.year - 1980) << 9 | (t.month as u16) << 5 | t.day as u16,
+			),
+			None => (0, 0),
+		};
+		entry[offset..offset + 2].copy_from_slice(&time.to_le_bytes());
+		entry[offset + 2..offset + 4].copy_from_slice(&date.to_le_bytes());
+	}
+}
+
+impl Display for FatTimestamp {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		write!(
+			f,
+			"{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+			self.year, self.month, self.day, self.hour, self.minute, self.second
+		)
+	}
+}
+
+/// A path, such as `EFI>BOOT>BOOTX64.EFI`. Build one from a string with [`path`].
+pub type Path<'a> = &'a [u8];
+
+/// Attribute bit: the file may not be modified
+pub const ATTRIBUTE_READ_ONLY: u8 = 0x01;
+/// Attribute bit: the file shouldn't be shown in normal directory listings
+pub const ATTRIBUTE_HIDDEN: u8 = 0x02;
+/// Attribute bit: the file belongs to the operating system
+pub const ATTRIBUTE_SYSTEM: u8 = 0x04;
+/// Attribute bit: the entry is a directory
+pub const ATTRIBUTE_DIRECTORY: u8 = 0x10;
+/// Attribute bit: the file has changed since it was last backed up
+pub const ATTRIBUTE_ARCHIVE: u8 = 0x20;
+
+impl FileInfo {
+	/// The long file name if there is one, and the 8.3 name otherwise
+	pub fn display_name(&self) -> &str {
+		self.long_name.as_deref().unwrap_or(self.name.to_str())
+	}
+
+	/// If the read-only attribute is set
+	pub fn is_read_only(&self) -> bool {
+		self.attributes & ATTRIBUTE_READ_ONLY != 0
+	}
+
+	/// If `name` is the 8.3 name or the long name of the entry, ignoring case
+	fn has_name(&self, name: &[u8]) -> bool {
+		names_equal(self.name.get_slice(), name)
+			|| self
+				.long_name
+				.as_ref()
+				.map_or(false, |long_name| names_equal(long_name.as_bytes(), name))
+	}
+
+	/// The path of this entry, given the path of the directory it is in
+	pub fn path_in(&self, directory_path: Path) -> Vec<u8> {
+		join_path(directory_path, self.name.get_slice())
+	}
+}
+
+/// Joins the path of a directory with the name of an entry in it.
+///
+/// The empty path is the root directory, so joining it with a name gives just the name.
+pub fn join_path(directory_path: Path, name: Path) -> Vec<u8> {
+	let mut path = directory_path.to_vec();
+	if path.last().map_or(false, |&b| !is_separator(b)) {
+		path.push(SEPARATOR_CHAR);
+	}
+	path.extend_from_slice(name);
+	path
+}
+
+/// Resolves `.` and `..` in `path`.
+///
+/// `.` is removed, and `..` removes the part before it. `..` in the root directory stays in the root directory.
+/// Leading, trailing and repeated separators are dropped, and all separators are replaced with `SEPARATOR_CHAR`.
+fn normalize_path(path: Path) -> Vec<u8> {
+	let mut parts: Vec<&[u8]> = Vec::new();
+	for part in path.split(|b| is_separator(*b)) {
+		match part {
+			b"" | b"." => {}
+			b".." => {
+				parts.pop();
+			}
+			part => parts.push(part),
+		}
+	}
+	parts.join(&SEPARATOR_CHAR)
+}
+
+/// Characters other than letters and digits which are allowed in 8.3 names
+const SHORT_NAME_SPECIAL_CHARS: &[u8] = b"!#$%&'()-@^_`{}~";
+
+/// Checks that `file_name` is a valid 8.3 name, and converts it to upper case, which is how 8.3 names are stored.
+///
+/// Fails with `FatError::InvalidName` if the name before the `.` isn't 1 to 8 characters, the extension is
+/// longer than 3 characters, or any character isn't a letter, a digit or one of [SHORT_NAME_SPECIAL_CHARS].
+fn to_short_name(file_name: &[u8]) -> Result<SVec<u8, 12>, FatError> {
+	let (name, extension) = match file_name.iter().rposition(|&b| b == b'.') {
+		Some(index) => (&file_name[..index], &file_name[index + 1..]),
+		None => (file_name, &[][..]),
+	};
+	let is_valid_char = |b: &u8| b.is_ascii_alphanumeric() || SHORT_NAME_SPECIAL_CHARS.contains(b);
+	if name.len() == 0
+		|| name.len() > 8
+		|| extension.len() > 3
+		|| !name.iter().all(is_valid_char)
+		|| !extension.iter().all(is_valid_char)
+	{
+		return Err(FatError::InvalidName);
+	}
+
+	let mut short_name = SVec::new();
+	for b in name {
+		short_name.push(b.to_ascii_uppercase());
+	}
+	if extension.len() > 0 {
+		short_name.push(b'.');
+		for b in extension {
+			short_name.push(b.to_ascii_uppercase());
+		}
+	}
+	Ok(short_name)
+}
+
+/// Characters which aren't allowed in long file names, besides control characters
+const LONG_NAME_INVALID_CHARS: &[u8] = b"\"*/:<>?\\|";
+
+/// Checks that `file_name` is a valid long file name.
+///
+/// Fails with `FatError::InvalidName` if it isn't UTF-8, is longer than 255 UTF-16 characters,
+/// only consists of `.` and spaces, or contains control characters or any of [LONG_NAME_INVALID_CHARS].
+fn to_long_name(file_name: &[u8]) -> Result<&str, FatError> {
+	let long_name = core::str::from_utf8(file_name).map_err(|_| FatError::InvalidName)?;
+	if long_name.encode_utf16().count() > 255
+		|| long_name.chars().all(|c| c == '.' || c == ' ')
+		|| long_name
+			.bytes()
+			.any(|b| b < 0x20 || LONG_NAME_INVALID_CHARS.contains(&b))
+	{
+		return Err(FatError::InvalidName);
+	}
+	Ok(long_name)
+}
+
+/// The `n`th 8.3 alias of `long_name`, like `LONGNA~1.TXT` for `long name.txt`. `n` must be 1 to 999999.
+///
+/// Spaces and leading `.`s are removed, the name is converted to upper case,
+/// and characters which aren't allowed in 8.3 names are replaced with `_`.
+/// The part before the last `.` is shortened to make room for the `~n` tail, and the extension to 3 characters.
+fn short_name_alias(long_name: &str, n: u32) -> SVec<u8, 12> {
+	let long_name = long_name.trim_start_matches('.');
+	let (name, extension) = match long_name.rfind('.') {
+		Some(index) => (&long_name[..index], &long_name[index + 1..]),
+		None => (long_name, ""),
+	};
+	let to_short_chars = |part: &str| -> Vec<u8> {
+		part
+			.chars()
+			.filter(|&c| c != ' ' && c != '.')
+			.map(|c| {
+				if c.is_ascii()
+					&& (c.is_ascii_alphanumeric() || SHORT_NAME_SPECIAL_CHARS.contains(&(c as u8)))
+				{
+					c.to_ascii_uppercase() as u8
+				} else {
+					b'_'
+				}
+			})
+			.collect()
+	};
+	let tail = format!("~{}", n);
+
+	let mut alias = SVec::new();
+	for b in to_short_chars(name).into_iter().take(8 - tail.len()) {
+		alias.push(b);
+	}
+	for b in tail.bytes() {
+		alias.push(b);
+	}
+	let extension = to_short_chars(extension);
+	if extension.len() > 0 {
+		alias.push(b'.');
+		for b in extension.into_iter().take(3) {
+			alias.push(b);
+		}
+	}
+	alias
+}
+
+/// The LFN entries holding `long_name`, in the order they are stored on disk, which is last part first.
+///
+/// `short_name` is the raw 8.3 name of the standard entry which follows them (see [`short_name_checksum`]).
+fn long_name_entries(long_name: &str, short_name: &[u8]) -> Vec<[u8; 32]> {
+	let chars: Vec<u16> = long_name.encode_utf16().collect();
+	let checksum = short_name_checksum(short_name);
+	let parts: Vec<&[u16]> = chars.chunks(13).collect();
+	parts
+		.iter()
+		.enumerate()
+		.rev()
+		.map(|(i, part)| {
+			// The name is ended with a 0 if it doesn't fill the last entry, and the rest is padded with 0xFFFF
+			let mut entry_chars = [0xFFFF; 13];
+			entry_chars[..part.len()].copy_from_slice(part);
+			if part.len() < 13 {
+				entry_chars[part.len()] = 0;
+			}
+			let order = i as u8 + 1;
+			DirectoryEntry::LongFileName {
+				order: if i == parts.len() - 1 {
+					order | 0x40
+				} else {
+					order
+				},
+				checksum,
+				chars: entry_chars,
+			}
+			.into()
+		})
+		.collect()
+}
+
+/// Are `a` and `b` the same file name? FAT file names are case insensitive, and 8.3 names are stored in upper case.
+fn names_equal(a: &[u8], b: &[u8]) -> bool {
+	a.eq_ignore_ascii_case(b)
+}
+
+/// Builds a path from a string, so callers don't need `b"..."` or `.as_bytes()`.
+///
+/// Either separator may be used, and the result is normalized like all other paths.
+pub fn path(path: &str) -> Vec<u8> {
+	normalize_path(path.as_bytes())
+}
+
+/// The size of a directory entry, in bytes
+const DIRECTORY_ENTRY_SIZE: usize = 32;
+
+/// The directory entry at `index` in `sector`
+fn entry_in_sector(sector: &mut [u8], index: usize) -> &mut [u8] {
+	&mut sector[index * DIRECTORY_ENTRY_SIZE..(index + 1) * DIRECTORY_ENTRY_SIZE]
+}
+
+/// Sectors which have been changed in memory, but not yet written to disk.
+///
+/// Changed sectors are collected here instead of being written as soon as they are unloaded,
+/// and all written together by [`Self::write_all`].
+struct DirtySectors {
+	/// Sorted by sector
+	sectors: Vec<(usize, [u8; 512])>,
+}
+
+impl DirtySectors {
+	const fn new() -> Self {
+		Self {
+			sectors: Vec::new(),
+		}
+	}
+
+	/// The number of sectors waiting to be written
+	fn len(&self) -> usize {
+		self.sectors.len()
+	}
+
+	/// The changed data of `sector`, if it has been changed
+	fn get(&self, sector: usize) -> Option<&[u8; 512]> {
+		self
+			.sectors
+			.binary_search_by_key(&sector, |(s, _)| *s)
+			.ok()
+			.map(|index| &self.sectors[index].1)
+	}
+
+	/// Stores `data` as the new data of `sector`, replacing any earlier change
+	fn insert(&mut self, sector: usize, data: &[u8]) {
+		let mut sector_data = [0; 512];
+		sector_data.copy_from_slice(data);
+		match self.sectors.binary_search_by_key(&sector, |(s, _)| *s) {
+			Ok(index) => self.sectors[index].1 = sector_data,
+			Err(index) => self.sectors.insert(index, (sector, sector_data)),
+		}
+	}
+
+	/// Writes all the sectors to `partition`, and forgets them.
+	///
+	/// Runs of consecutive sectors are written with a single transfer.
+	/// If a write fails, the sectors which haven't been written yet are kept, so they can be written later.
+	unsafe fn write_all(&mut self, partition: u8) -> Result<(), PataError> {
+		let mut run: Vec<u8> = Vec::new();
+		let mut run_start = 0;
+		// The index in `sectors` of the first sector of the run
+		let mut run_start_index = 0;
+		for (i, (sector, data)) in self.sectors.iter().enumerate() {
+			if run.len() == 0 {
+				run_start = *sector;
+				run_start_index = i;
+			}
+			run.extend_from_slice(data);
+			let run_continues = self
+				.sectors
+				.get(i + 1)
+				.map_or(false, |(next_sector, _)| *next_sector == sector + 1);
+			if !run_continues {
+				if let Err(e) = super::partitions::write_sectors(partition, run_start, &run) {
+					self.sectors.drain(..run_start_index);
+					return Err(e);
+				}
+				#[cfg(test)]
+				SECTOR_WRITES.fetch_add(run.len() / 512, core::sync::atomic::Ordering::Relaxed);
+				run.clear();
+			}
+		}
+		self.sectors.clear();
+		Ok(())
+	}
+}
 
 struct FileAllocationTable {
 	version: FatVersion,
 	/// The number of FAT sectors
 	sector_count: usize,
+	/// The index of the partition the file system is on
+	partition: u8,
 	/// The offset of were the FAT partion begins on the harddisk
 	fat_offset: usize,
+	/// The number of copies of the FAT, which follow each other.
+	///
+	/// Only the first one is read, but changes are written to all of them.
+	copy_count: usize,
 	/// Relative to `fat_offset` (harddisk sector)
 	currently_loaded_sector: usize,
 	/// Buffer two sectors from the harddisk.
 	///
 	/// We only ever assume one is loaded, but since a cluster could be on a sector boundry, this is to make sure that circumsatance doesn't cause complications.
 	buffer: [u8; 1024],
+	/// The two sectors as they are in `dirty_sectors` or on disk, to tell which have been changed in `buffer`
+	stored_buffer: [u8; 1024],
+	/// Changed FAT sectors, relative to the start of the partition
+	dirty_sectors: DirtySectors,
+	/// Where the next search for an empty cluster starts.
+	///
+	/// Set to the cluster after the last one found, so consecutive allocations don't rescan
+	/// the start of the FAT, and a file's clusters tend to end up next to each other.
+	next_free_hint: u32,
+	/// The number of free clusters, if it is known.
+	///
+	/// Kept up to date by [`Self::set_next_cluster`], and written to the FSInfo sector by [`Driver::sync`].
+	free_count: Option<u32>,
 }
 
 impl FileAllocationTable {
-	fn new(version: FatVersion, sector_count: usize, fat_offset: usize) -> Self {
-		Self {
+	/// Fails if the start of the FAT can't be read
+	fn new(
+		version: FatVersion,
+		partition: u8,
+		sector_count: usize,
+		fat_offset: usize,
+		copy_count: usize,
+	) -> Result<Self, ()> {
+		let mut buffer = [0; 1024];
+		unsafe {
+			super::partitions::read_sectors(partition, fat_offset, &mut buffer).map_err(|_| ())?;
+		}
+		Ok(Self {
 			version,
+			partition,
 			sector_count,
 			fat_offset,
+			copy_count,
 			currently_loaded_sector: 0,
-			buffer: {
-				let mut buffer = [0; 1024];
-				unsafe {
-					super::partitions::read_sectors(0, fat_offset, &mut buffer);
-				}
-				buffer
-			},
-		}
+			buffer,
+			stored_buffer: buffer,
+			dirty_sectors: DirtySectors::new(),
+			next_free_hint: 2,
+			free_count: None,
+		})
 	}
 
-	/// Write the buffer to the disk
+	/// Puts the sectors of the buffer which have been changed in `dirty_sectors`, once for every copy of the FAT
 	/// Allways run this when changing sectors
 	/// (Or use `load_sector_containing`)
 	fn flush(&mut self) {
-		unsafe {
-			super::partitions::write_sectors(
-				0,
-				self.fat_offset + self.currently_loaded_sector,
-				&self.buffer,
-			);
+		let halves = self
+			.buffer
+			.chunks_exact(512)
+			.zip(self.stored_buffer.chunks_exact_mut(512));
+		for (i, (data, stored)) in halves.enumerate() {
+			let sector = self.currently_loaded_sector + i;
+			// The second half may be past the end of the FAT, but then it is never changed
+			if data != stored && sector < self.sector_count {
+				for copy in 0..self.copy_count {
+					let copy_offset = self.fat_offset + copy * self.sector_count;
+					self.dirty_sectors.insert(copy_offset + sector, data);
+				}
+				stored.copy_from_slice(data);
+			}
 		}
 	}
 
+	/// Writes all changed FAT sectors to disk
+	fn sync(&mut self) -> Result<(), PataError> {
+		self.flush();
+		unsafe { self.dirty_sectors.write_all(self.partition) }
+	}
+
 	/// Loads the sector containing `cluster`, if there is one.
 	fn load_sector_containing(&mut self, cluster: u32) -> Result<(), ()> {
 		let bit_offset = cluster as usize * self.version.get_cluster_bit_size();
@@ -78,13 +512,20 @@ impl FileAllocationTable {
 		}
 		if sector_containing_cluster != self.currently_loaded_sector {
 			self.flush();
+			let first_sector = self.fat_offset + sector_containing_cluster;
 			unsafe {
-				super::partitions::read_sectors(
-					0,
-					self.fat_offset + sector_containing_cluster,
-					&mut self.buffer,
-				);
+				super::partitions::read_sectors(self.partition, first_sector, &mut self.buffer)
+					.map_err(|_| ())?;
 			}
+			#[cfg(test)]
+			FAT_SECTOR_READS.fetch_add(2, core::sync::atomic::Ordering::Relaxed);
+			// Changes which haven't been written yet replace what was read
+			for (i, data) in self.buffer.chunks_exact_mut(512).enumerate() {
+				if let Some(dirty_data) = self.dirty_sectors.get(first_sector + i) {
+					data.copy_from_slice(dirty_data);
+				}
+			}
+			self.stored_buffer = self.buffer;
 			self.currently_loaded_sector = sector_containing_cluster;
 		}
 		Ok(())
@@ -111,17 +552,17 @@ impl FileAllocationTable {
 			}
 			FatVersion::Fat16 => (
 				u16::from_le_bytes([
-					self.buffer[relative_byte_offset * 2],
-					self.buffer[relative_byte_offset * 2 + 1],
+					self.buffer[relative_byte_offset],
+					self.buffer[relative_byte_offset + 1],
 				]) as u32,
 				0xFFF8,
 			),
 			FatVersion::Fat32 { .. } => (
 				u32::from_le_bytes([
-					self.buffer[relative_byte_offset * 4],
-					self.buffer[relative_byte_offset * 4 + 1],
-					self.buffer[relative_byte_offset * 4 + 2],
-					self.buffer[relative_byte_offset * 4 + 3],
+					self.buffer[relative_byte_offset],
+					self.buffer[relative_byte_offset + 1],
+					self.buffer[relative_byte_offset + 2],
+					self.buffer[relative_byte_offset + 3],
 				]),
 				0x0FFF_FFF8,
 			),
@@ -135,10 +576,19 @@ impl FileAllocationTable {
 	}
 
 	/// Linear search for the next empty cluster.
-	fn find_empty_cluster(&mut self, start_cluster: u32) -> Option<u32> {
-		for cluster in start_cluster..self.sector_count as u32 * self.clusters_per_sector() as u32 {
+	///
+	/// Starts at `next_free_hint`, and wraps around to cluster 2 if nothing was found after it.
+	fn find_empty_cluster(&mut self) -> Option<u32> {
+		let cluster_count = self.sector_count as u32 * self.clusters_per_sector() as u32;
+		let start_cluster = if (2..cluster_count).contains(&self.next_free_hint) {
+			self.next_free_hint
+		} else {
+			2
+		};
+		for cluster in (start_cluster..cluster_count).chain(2..start_cluster) {
 			self.load_sector_containing(cluster).ok()?;
 			if self.get_next_cluster(cluster) == Some(0) {
+				self.next_free_hint = cluster + 1;
 				return Some(cluster);
 			}
 		}
@@ -148,6 +598,7 @@ impl FileAllocationTable {
 	/// Set the `next_cluster` as being after `cluster` in the chain.
 	fn set_next_cluster(&mut self, cluster: u32, next_cluster: Option<u32>) -> Result<(), ()> {
 		self.load_sector_containing(cluster)?;
+		let was_free = self.get_next_cluster(cluster) == Some(0);
 
 		// let clusters_per_sector = self.clusters_per_sector();
 		// let relative_cluster = cluster as usize - self.currently_loaded_sector * clusters_per_sector;
@@ -175,15 +626,23 @@ impl FileAllocationTable {
 					.copy_from_slice(&num.to_le_bytes());
 			}
 			FatVersion::Fat16 => {
-				self.buffer[relative_byte_offset * 2..(relative_byte_offset + 1) * 2]
+				self.buffer[relative_byte_offset..relative_byte_offset + 2]
 					.copy_from_slice(&(next_cluster.unwrap_or(0xFFFF) as u16).to_le_bytes());
 			}
 			FatVersion::Fat32 { .. } => {
-				self.buffer[relative_byte_offset * 4..(relative_byte_offset + 1) * 4]
+				self.buffer[relative_byte_offset..relative_byte_offset + 4]
 					.copy_from_slice(&next_cluster.unwrap_or(0x0FFF_FFFF).to_le_bytes());
 			}
 		};
 
+		if let Some(free_count) = &mut self.free_count {
+			match (was_free, next_cluster == Some(0)) {
+				(true, false) => *free_count = free_count.saturating_sub(1),
+				(false, true) => *free_count += 1,
+				_ => {}
+			}
+		}
+
 		Ok(())
 	}
 
@@ -201,6 +660,53 @@ impl FileAllocationTable {
 	}
 }
 
+/// The FSInfo sector of FAT32, which keeps track of the free clusters so they don't have to be counted
+struct FsInfo {
+	/// The number of free clusters, `None` if unknown
+	free_count: Option<u32>,
+	/// The cluster to start looking for a free cluster at, `None` if unknown
+	next_free: Option<u32>,
+}
+
+impl FsInfo {
+	const LEAD_SIGNATURE: u32 = 0x4161_5252;
+	const STRUCT_SIGNATURE: u32 = 0x6141_7272;
+	/// The value of a field which is unknown
+	const UNKNOWN: u32 = 0xFFFF_FFFF;
+
+	/// Reads the FSInfo from `sector`. Returns `None` if the signatures are wrong.
+	fn read(sector: &[u8]) -> Option<Self> {
+		let read_u32 = |offset: usize| {
+			u32::from_le_bytes([
+				sector[offset],
+				sector[offset + 1],
+				sector[offset + 2],
+				sector[offset + 3],
+			])
+		};
+		if read_u32(0) != Self::LEAD_SIGNATURE || read_u32(0x1E4) != Self::STRUCT_SIGNATURE {
+			return None;
+		}
+		let known = |value| {
+			if value == Self::UNKNOWN {
+				None
+			} else {
+				Some(value)
+			}
+		};
+		Some(Self {
+			free_count: known(read_u32(0x1E8)),
+			next_free: known(read_u32(0x1EC)),
+		})
+	}
+
+	/// Writes the fields to `sector`, which must already be a valid FSInfo sector
+	fn write(&self, sector: &mut [u8]) {
+		sector[0x1E8..0x1EC].copy_from_slice(&self.free_count.unwrap_or(Self::UNKNOWN).to_le_bytes());
+		sector[0x1EC..0x1F0].copy_from_slice(&self.next_free.unwrap_or(Self::UNKNOWN).to_le_bytes());
+	}
+}
+
 /// The FAT header
 #[derive(Debug)]
 struct Header {
@@ -241,6 +747,9 @@ impl FatVersion {
 
 impl Header {
 	/// Tries to read a a header from the harddisk
+	///
+	/// Fails if the sector size isn't 512, or if any of the values which can't be 0
+	/// in a FAT file system are, which means the sector is something else, such as garbage.
 	fn try_new(sector: &[u8]) -> Result<Self, ()> {
 		let version = if sector[0x16] == 0 {
 			let root_dir_cluster =
@@ -265,7 +774,7 @@ impl Header {
 		};
 
 		if &sector[0x0B..0x0D] != &512u16.to_le_bytes() {
-			panic!("Sector size is not 512"); // Shouldn't this be an Error?
+			return Err(());
 		}
 
 		let sectors_per_cluster = sector[0x0D] as _;
@@ -276,7 +785,12 @@ impl Header {
 
 		let root_dir_entries = u16::from_le_bytes([sector[0x11], sector[0x12]]) as _;
 
-		let total_sectors = u16::from_le_bytes([sector[0x13], sector[0x14]]) as _;
+		// The 16-bit count is 0 if the count doesn't fit in it, which is always the case for FAT32,
+		// and then the 32-bit count is used instead
+		let total_sectors = match u16::from_le_bytes([sector[0x13], sector[0x14]]) {
+			0 => u32::from_le_bytes([sector[0x20], sector[0x21], sector[0x22], sector[0x23]]) as _,
+			count => count as _,
+		};
 
 		let sectors_per_fat = if let FatVersion::Fat32 { .. } = version {
 			u32::from_le_bytes([sector[0x24], sector[0x25], sector[0x26], sector[0x27]])
@@ -300,14 +814,14 @@ impl Header {
 				label.push(*b);
 			}
 
-			while label[label.len() - 1] == b' ' {
+			while label.len() > 0 && label[label.len() - 1] == b' ' {
 				label.pop();
 			}
 
 			label
 		};
 
-		Ok(Header {
+		let header = Header {
 			oem_ident,
 			sectors_per_cluster,
 			reserved_sectors,
@@ -317,18 +831,45 @@ impl Header {
 			sectors_per_fat,
 			label,
 			fat_version: version,
-		})
+		};
+
+		if !header.sectors_per_cluster.is_power_of_two()
+			|| header.reserved_sectors == 0
+			|| header.fat_count == 0
+			|| header.total_sectors == 0
+			|| header.sectors_per_fat == 0
+		{
+			return Err(());
+		}
+		if let FatVersion::Fat32 {
+			root_dir_cluster, ..
+		} = version
+		{
+			// Clusters 0 and 1 are reserved
+			if root_dir_cluster < 2 {
+				return Err(());
+			}
+		}
+
+		Ok(header)
 	}
 }
 
 struct Driver {
-	partition: usize,
+	/// The index of the partition the file system is on
+	partition: u8,
 	header: Header,
 	fat: FileAllocationTable,
 	/// FAT sector
 	current_loaded_sector: usize,
 	/// Unlike the `fat`, there is no worry of breaching sector boundries here
 	buffer: [u8; 512],
+	/// The loaded sector as it is in `dirty_sectors` or on disk, to tell if `buffer` has been changed.
+	///
+	/// `None` if the sector was loaded for overwriting, and so is always changed.
+	stored_buffer: Option<[u8; 512]>,
+	/// Changed sectors which aren't in the FAT, written to disk by [`Self::sync`]
+	dirty_sectors: DirtySectors,
 }
 
 static mut DRIVER: Driver = Driver::uninititalized();
@@ -350,13 +891,21 @@ impl Driver {
 			},
 			fat: FileAllocationTable {
 				version: FatVersion::Fat12,
+				partition: 0,
 				sector_count: 0,
 				fat_offset: 0,
+				copy_count: 0,
 				currently_loaded_sector: 0,
 				buffer: [0; 1024],
+				stored_buffer: [0; 1024],
+				dirty_sectors: DirtySectors::new(),
+				next_free_hint: 2,
+				free_count: None,
 			},
 			current_loaded_sector: 0,
 			buffer: [0; 512],
+			stored_buffer: Some([0; 512]),
+			dirty_sectors: DirtySectors::new(),
 		}
 	}
 
@@ -368,55 +917,125 @@ impl Driver {
 	///
 	/// Requires partitions to already be initialized.
 	unsafe fn initialize(&mut self) {
-		for part in super::partitions::list_partitions() {
-			let start = part.start_sector();
-			let mut sector = [0; 512];
-			super::partitions::read_sectors(part.index(), 0, &mut sector);
-			if let Ok(header) = Header::try_new(&sector) {
-				self.header = header;
-				self.fat = FileAllocationTable::new(
-					self.header.fat_version,
-					self.header.total_sectors,
-					self.header.reserved_sectors,
-				);
-				super::partitions::read_sectors(part.index(), 0, &mut self.buffer);
-				// println!("{:#?}", self.header);
+		// Partitions which are supposed to contain a FAT file system are tried first
+		let partitions = super::partitions::list_partitions();
+		let fat_typed = partitions.iter().filter(|p| p.is_fat_type());
+		let others = partitions.iter().filter(|p| !p.is_fat_type());
+		for part in fat_typed.chain(others) {
+			// A partition which can't be read, or doesn't contain a FAT file system, is skipped
+			if self.mount(part.index()).is_ok() {
 				break;
 			}
-			// todo!("Check if sector counts are the same for the header and the partition");
+		}
+	}
+
+	/// Reads the FAT file system on the partition with the index `partition`.
+	///
+	/// The driver has to be uninitialized, or everything it has loaded may be mixed up with the new file system.
+	/// Fails with `FatError::NoFileSystem` if there is no such partition, if it can't be read,
+	/// or if it doesn't contain a FAT file system.
+	unsafe fn mount(&mut self, partition: u8) -> Result<(), FatError> {
+		if partition as usize >= super::partitions::list_partitions().len() {
+			return Err(FatError::NoFileSystem);
+		}
+		let mut sector = [0; 512];
+		super::partitions::read_sectors(partition, 0, &mut sector)
+			.map_err(|_| FatError::NoFileSystem)?;
+		let header = Header::try_new(&sector).map_err(|_| FatError::NoFileSystem)?;
+		// todo!("Check if sector counts are the same for the header and the partition");
+
+		// The FAT is only as big as `sectors_per_fat`, the sectors after it are the next FAT or data
+		self.fat = FileAllocationTable::new(
+			header.fat_version,
+			partition,
+			header.sectors_per_fat,
+			header.reserved_sectors,
+			header.fat_count,
+		)
+		.map_err(|_| FatError::NoFileSystem)?;
+		self.partition = partition;
+		self.header = header;
+		self.buffer = sector;
+		self.current_loaded_sector = 0;
+		self.stored_buffer = Some(self.buffer);
+		self.load_fsinfo();
+		Ok(())
+	}
+
+	/// Uses the free cluster count and the next free cluster hint of the FSInfo sector, if there is one.
+	///
+	/// If the FSInfo sector can't be read, the free clusters are counted when needed instead.
+	unsafe fn load_fsinfo(&mut self) {
+		if let Ok(Some(fsinfo)) = self.read_fsinfo() {
+			if let Some(next_free) = fsinfo.next_free {
+				self.fat.next_free_hint = next_free;
+			}
+			// A count larger than the number of clusters can't be right, so it is counted again when needed
+			let cluster_count = self.data_cluster_count();
+			self.fat.free_count = fsinfo
+				.free_count
+				.filter(|&count| count as usize <= cluster_count);
 		}
 	}
 
 	/// Load a particular (FAT) sector
-	unsafe fn load_sector(&mut self, sector: usize) {
+	///
+	/// A sector which has been changed but not yet written to disk is loaded from `dirty_sectors`.
+	/// Fails with `FatError::Disk` if the sector can't be read, in which case the loaded sector stays loaded.
+	unsafe fn load_sector(&mut self, sector: usize) -> Result<(), FatError> {
+		if self.current_loaded_sector == sector {
+			return Ok(());
+		}
+		self.flush();
+		match self.dirty_sectors.get(sector) {
+			Some(data) => self.buffer = *data,
+			None => {
+				// Read into a copy, so a failed read doesn't overwrite the loaded sector
+				let mut buffer = [0; 512];
+				super::partitions::read_sectors(self.partition, sector, &mut buffer)
+					.map_err(FatError::Disk)?;
+				self.buffer = buffer;
+			}
+		}
+		self.stored_buffer = Some(self.buffer);
+		self.current_loaded_sector = sector;
+		Ok(())
+	}
+
+	/// Makes `sector` the loaded sector without reading it from disk.
+	///
+	/// Only use this when the whole sector is about to be overwritten,
+	/// as the buffer will still contain the data of the previously loaded sector.
+	unsafe fn load_sector_for_overwrite(&mut self, sector: usize) {
 		if self.current_loaded_sector == sector {
 			return;
 		}
 		self.flush();
-		super::partitions::read_sectors(self.partition as _, sector, &mut self.buffer);
+		self.stored_buffer = None;
 		self.current_loaded_sector = sector;
 	}
 
 	/// Returns the files/directories inside a cluster
-	unsafe fn get_entries_from_cluster(&mut self, cluster: u32) -> Vec<FileInfo> {
+	unsafe fn get_entries_from_cluster(&mut self, cluster: u32) -> Result<Vec<FileInfo>, FatError> {
 		let root_dir_sectors = (self.header.root_dir_entries * 32 + 511) / 512;
 		let first_data_sector = self.header.reserved_sectors
 			+ self.header.fat_count * self.header.sectors_per_fat
 			+ root_dir_sectors;
 
 		let mut file_entries = Vec::new();
+		let mut long_name = LongNameBuilder::new();
 
 		let mut current_cluster = cluster;
+		let mut visited_clusters = 0;
 
 		loop {
 			let cluster_sector =
 				(current_cluster as usize - 2) * self.header.sectors_per_cluster + first_data_sector;
 			for sector in cluster_sector..cluster_sector + self.header.sectors_per_cluster {
-				self.load_sector(sector);
+				self.load_sector(sector)?;
 
-				for i in 0..(512 / 32) {
-					let entry = &self.buffer[i * 32..(i + 1) * 32];
-					let entry: DirectoryEntry = entry.try_into().unwrap();
+				for raw_entry in self.buffer.chunks_exact(DIRECTORY_ENTRY_SIZE) {
+					let entry: DirectoryEntry = raw_entry.try_into().unwrap();
 
 					match entry {
 						DirectoryEntry::Standard {
@@ -424,23 +1043,37 @@ impl Driver {
 							attributes,
 							first_cluster,
 							file_size,
+							created,
+							modified,
 						} => {
 							// println!("Name: {}", file_name.to_str());
 							file_entries.push(FileInfo {
 								name: file_name,
 								size: file_size as _,
 								is_directory: attributes & 0x10 != 0,
+								attributes,
+								long_name: long_name.finish(&raw_entry[..11]),
+								created,
+								modified,
 								first_cluster,
 							});
 						}
-						DirectoryEntry::LongFileName {} => continue,
-						DirectoryEntry::Unused => continue,
-						DirectoryEntry::Empty => break,
+						DirectoryEntry::LongFileName {
+							order,
+							checksum,
+							chars,
+						} => long_name.add_entry(order, checksum, &chars),
+						DirectoryEntry::Unused => long_name.reset(),
+						// An empty entry marks the end of the directory,
+						// anything after it (even in later sectors or clusters) is garbage.
+						DirectoryEntry::Empty => return Ok(file_entries),
 					}
 				}
 			}
 
-			if let Some(next_cluster) = self.fat.get_next_cluster(current_cluster) {
+			if let Some(next_cluster) =
+				self.next_cluster_bounded(current_cluster, &mut visited_clusters)?
+			{
 				current_cluster = next_cluster;
 				continue;
 			} else {
@@ -448,29 +1081,31 @@ impl Driver {
 			}
 		}
 
-		file_entries
+		Ok(file_entries)
 	}
 
 	/// Returns the files/directories of specified path
 	///
 	/// Empty path gives root directory
 	unsafe fn get_entries(&mut self, path: &[u8]) -> Result<Vec<FileInfo>, FatError> {
+		let path = &normalize_path(path)[..];
+
 		unsafe fn get_entries_2(
 			s: &mut Driver,
 			entries: &[FileInfo],
 			path: &[u8],
 		) -> Result<Vec<FileInfo>, FatError> {
-			let mut parts = path.splitn(2, |v| *v == SEPARATOR_CHAR);
+			let mut parts = path.splitn(2, |v| is_separator(*v));
 			let first_part = parts.next().unwrap();
 			let rest_path = parts.next().unwrap_or(&[]);
 
 			for entry in entries {
-				if entry.name.get_slice() == first_part {
+				if entry.has_name(first_part) {
 					if entry.is_directory {
 						let entries = if entry.first_cluster == 0 {
-							s.get_root_entries()
+							s.get_root_entries()?
 						} else {
-							s.get_entries_from_cluster(entry.first_cluster)
+							s.get_entries_from_cluster(entry.first_cluster)?
 						};
 
 						if rest_path.len() > 0 {
@@ -488,39 +1123,31 @@ impl Driver {
 		}
 
 		if path.len() == 0 {
-			Ok(self.get_root_entries())
+			self.get_root_entries()
 		} else {
-			let root_entries = self.get_root_entries();
+			let root_entries = self.get_root_entries()?;
 			get_entries_2(self, &root_entries, path)
 		}
 	}
 
 	/// FAT12/16 has special root directories, handled here
-	unsafe fn get_root_entries(&mut self) -> Vec<FileInfo> {
+	unsafe fn get_root_entries(&mut self) -> Result<Vec<FileInfo>, FatError> {
 		match self.header.fat_version {
 			FatVersion::Fat32 {
 				root_dir_cluster, ..
 			} => self.get_entries_from_cluster(root_dir_cluster),
 			_ => {
-				let root_dir_sectors = (self.header.root_dir_entries * 32 + 511) / 512;
-				let data_sector = self.header.total_sectors
-					- (self.header.reserved_sectors
-						+ self.header.fat_count * self.header.sectors_per_fat
-						+ root_dir_sectors);
-				let first_data_sector = self.header.reserved_sectors
-					+ self.header.fat_count * self.header.sectors_per_fat
-					+ root_dir_sectors;
-				let first_root_dir_sector = first_data_sector - root_dir_sectors;
-
 				let mut file_entries = Vec::<FileInfo>::new();
+				let mut long_name = LongNameBuilder::new();
 
-				for i in 0.. {
-					let sector = first_root_dir_sector + (i * 32 / 512);
-					let index = i % (512 / 32);
+				// A full root directory has no empty entry at the end,
+				// so the loop also has to stop at the end of the root directory region.
+				for i in 0..self.header.root_dir_entries {
+					let (sector, byte_offset) = self.root_dir_entry_location(i);
 
-					self.load_sector(sector);
-					let entry = &self.buffer[index * 32..(index + 1) * 32];
-					let entry: DirectoryEntry = entry.try_into().unwrap();
+					self.load_sector(sector)?;
+					let raw_entry = &self.buffer[byte_offset..byte_offset + 32];
+					let entry: DirectoryEntry = raw_entry.try_into().unwrap();
 
 					match entry {
 						DirectoryEntry::Standard {
@@ -528,21 +1155,31 @@ impl Driver {
 							attributes,
 							first_cluster,
 							file_size,
+							created,
+							modified,
 						} => {
 							file_entries.push(FileInfo {
 								name: file_name,
 								size: file_size as _,
 								is_directory: attributes & 0x10 != 0,
+								attributes,
+								long_name: long_name.finish(&raw_entry[..11]),
+								created,
+								modified,
 								first_cluster,
 							});
 						}
-						DirectoryEntry::LongFileName {} => continue,
-						DirectoryEntry::Unused => continue,
+						DirectoryEntry::LongFileName {
+							order,
+							checksum,
+							chars,
+						} => long_name.add_entry(order, checksum, &chars),
+						DirectoryEntry::Unused => long_name.reset(),
 						DirectoryEntry::Empty => break,
 					}
 				}
 
-				return file_entries;
+				return Ok(file_entries);
 			}
 		}
 	}
@@ -567,13 +1204,14 @@ impl Driver {
 			+ root_dir_sectors;
 
 		let mut current_cluster = file_info.first_cluster;
+		let mut visited_clusters = 0;
 
 		let mut cluster_count = 0;
 		loop {
 			let cluster_sector =
 				(current_cluster as usize - 2) * self.header.sectors_per_cluster + first_data_sector;
 			for i in 0..self.header.sectors_per_cluster {
-				self.load_sector(cluster_sector + i);
+				self.load_sector(cluster_sector + i)?;
 
 				let offset = (cluster_count * self.header.sectors_per_cluster + i) * 512;
 				let rest_size = file_info.size.saturating_sub(offset);
@@ -583,7 +1221,9 @@ impl Driver {
 				}
 			}
 
-			if let Some(next_cluster) = self.fat.get_next_cluster(current_cluster) {
+			if let Some(next_cluster) =
+				self.next_cluster_bounded(current_cluster, &mut visited_clusters)?
+			{
 				current_cluster = next_cluster;
 			} else {
 				break;
@@ -595,17 +1235,30 @@ impl Driver {
 		Ok(file_info.size)
 	}
 
+	/// Loads the data from a file at `path` into a newly allocated `Vec` of the right size.
+	///
+	/// # Safety
+	///
+	/// The dynamic allocator must be initialized.
+	unsafe fn read_file_to_vec(&mut self, path: Path) -> Result<Vec<u8>, FatError> {
+		let file_info = self.get_file_info(path)?;
+		let mut data = vec![0; file_info.size];
+		self.read_file_at(&file_info, 0, &mut data)?;
+		Ok(data)
+	}
+
 	/// Returns information about the file at `path`
 	unsafe fn get_entry_info(&mut self, path: &[u8]) -> Result<FileInfo, FatError> {
-		println!(
-			"Getting entry info of '{}'",
-			core::str::from_utf8(path).unwrap()
-		);
+		let path = &normalize_path(path)[..];
 		if path.len() == 0 {
 			return Ok(FileInfo {
 				name: SVec::new(),
 				size: 0,
 				is_directory: true,
+				attributes: ATTRIBUTE_DIRECTORY,
+				long_name: None,
+				created: None,
+				modified: None,
 				first_cluster: if let FatVersion::Fat32 {
 					root_dir_cluster, ..
 				} = self.header.fat_version
@@ -620,7 +1273,7 @@ impl Driver {
 
 		let mut last_separator_index = None;
 		for (i, &b) in path.iter().enumerate() {
-			if b == SEPARATOR_CHAR {
+			if is_separator(b) {
 				last_separator_index = Some(i);
 			}
 		}
@@ -636,12 +1289,11 @@ impl Driver {
 
 		let entries = self.get_entries(dir_path)?;
 		for entry in &entries {
-			if entry.name.get_slice() == file_name {
+			if entry.has_name(file_name) {
 				return Ok(entry.clone());
 			}
 		}
 
-		println!("Path '{}' not found", core::str::from_utf8(path).unwrap());
 		Err(FatError::PathNotFound)
 	}
 
@@ -667,67 +1319,141 @@ impl Driver {
 
 	/// Creates a empty file at `path`
 	///
-	/// Assumes 8.3 filename
+	/// A valid 8.3 name is stored in upper case, any other name as a long file name (see [`Self::add_entry`]).
 	///
 	/// (aka `touch`)
+	///
+	/// Fails with `FatError::AlreadyExists` if there already is a file or directory at `path`,
+	/// and with `FatError::InvalidName` if the name isn't a valid file name.
 	unsafe fn create_empty_file(&mut self, path: Path) -> Result<FileInfo, FatError> {
-		assert!(self.get_entry_info(path).is_err());
+		self.create_file(path, 0)
+	}
+
+	/// Like [`Self::create_empty_file`], but the directory entry gets the attribute bits `attributes`.
+	///
+	/// If [ATTRIBUTE_DIRECTORY] is set, a directory is created instead of a file.
+	unsafe fn create_file(&mut self, path: Path, attributes: u8) -> Result<FileInfo, FatError> {
+		if self.get_entry_info(path).is_ok() {
+			return Err(FatError::AlreadyExists);
+		}
 
-		let (mut dir_path, mut file_name) = path.split_last_2(&SEPARATOR_CHAR);
+		let (mut dir_path, mut file_name) = split_path(path);
 		if file_name.len() == 0 {
 			core::mem::swap(&mut dir_path, &mut file_name);
 		}
 
+		if attributes & ATTRIBUTE_DIRECTORY != 0 {
+			let mut file_info = self.create_directory(path)?;
+			file_info.attributes |= attributes;
+			self.update_file_info(path, file_info.clone())?;
+			return Ok(file_info);
+		}
+
 		if let Err(FatError::PathNotFound) = self.get_directory_info(dir_path) {
-			println!("Creating directory");
 			self.create_directory(dir_path)?;
-			println!("Created directory");
 		}
 
-		let mut name = SVec::<u8, 8>::new();
-		let mut ext = SVec::<u8, 3>::new();
+		self.add_entry(dir_path, file_name, attributes, 0)
+	}
 
-		let (bare_name, extension) = file_name.split_last_2(&b'.');
-		for &b in bare_name {
-			name.push(b);
-		}
-		for _ in name.len()..name.capacity() {
-			name.push(b' ');
-		}
-		for &b in extension {
-			ext.push(b);
-		}
-		for _ in ext.len()..ext.capacity() {
-			ext.push(b' ');
+	/// Adds an entry called `file_name` to the directory at `dir_path`, without checking if there already is one.
+	///
+	/// If `file_name` isn't a valid 8.3 name, it is stored as a long file name,
+	/// together with an 8.3 alias (see [`short_name_alias`]) which no other entry in the directory has.
+	///
+	/// Fails with `FatError::InvalidName` if `file_name` isn't a valid long file name either.
+	unsafe fn add_entry(
+		&mut self,
+		dir_path: Path,
+		file_name: &[u8],
+		attributes: u8,
+		first_cluster: u32,
+	) -> Result<FileInfo, FatError> {
+		let (short_name, long_name) = match to_short_name(file_name) {
+			Ok(short_name) => (short_name, None),
+			Err(_) => {
+				let long_name = to_long_name(file_name)?;
+				let entries = self.get_entries(dir_path)?;
+				let short_name = (1..=999_999)
+					.map(|n| short_name_alias(long_name, n))
+					.find(|alias| {
+						!entries
+							.iter()
+							.any(|entry| names_equal(entry.name.get_slice(), alias.get_slice()))
+					})
+					// Every alias is taken, so the directory already has (way too) many similar names
+					.ok_or(FatError::AlreadyExists)?;
+				(short_name, Some(long_name))
+			}
+		};
+
+		let standard_entry: [u8; 32] = DirectoryEntry::Standard {
+			file_name: short_name.clone(),
+			attributes,
+			first_cluster,
+			file_size: 0,
+			created: None,
+			modified: None,
 		}
+		.into();
+		let mut entries = match long_name {
+			Some(long_name) => long_name_entries(long_name, &standard_entry[..11]),
+			None => Vec::new(),
+		};
+		entries.push(standard_entry);
+		self.write_entries(dir_path, &entries)?;
+
+		Ok(FileInfo {
+			name: short_name,
+			size: 0,
+			is_directory: attributes & ATTRIBUTE_DIRECTORY != 0,
+			attributes,
+			long_name: long_name.map(String::from),
+			created: None,
+			modified: None,
+			first_cluster,
+		})
+	}
 
-		for entry_slice in EntryCreatingIterator::new(self, dir_path)? {
+	/// Writes `entries` to consecutive unused entries in the directory at `dir_path`.
+	///
+	/// The directory gets a new cluster if there isn't room at the end of it.
+	unsafe fn write_entries(&mut self, dir_path: Path, entries: &[[u8; 32]]) -> Result<(), FatError> {
+		let mut run_start = 0;
+		let mut run_length = 0;
+		for (index, entry_slice) in EntryCreatingIterator::new(self, dir_path)?.enumerate() {
+			let entry_slice = entry_slice?;
 			let dir_entry: DirectoryEntry = entry_slice[..].try_into().unwrap();
 			match dir_entry {
-				DirectoryEntry::Standard { .. } | DirectoryEntry::LongFileName { .. } => continue,
 				DirectoryEntry::Unused | DirectoryEntry::Empty => {
-					let mut new_entry = [0u8; 32];
-
-					new_entry[0..8].copy_from_slice(name.get_slice());
-					new_entry[8..11].copy_from_slice(ext.get_slice());
-
-					entry_slice.copy_from_slice(&new_entry);
-
-					return Ok(FileInfo {
-						name: file_name.try_into().unwrap(),
-						size: 0,
-						is_directory: false,
-						first_cluster: 0,
-					});
+					if run_length == 0 {
+						run_start = index;
+					}
+					run_length += 1;
+					if run_length == entries.len() {
+						break;
+					}
 				}
+				DirectoryEntry::Standard { .. } | DirectoryEntry::LongFileName { .. } => run_length = 0,
 			}
 		}
+		if run_length < entries.len() {
+			return Err(FatError::FileSystemFull);
+		}
 
-		Err(FatError::FileSystemFull)
+		// Each entry has to be written before the next one is fetched, as that may load another sector.
+		// `entries` comes first in the zip, so no entry is fetched after the last one is written.
+		let entry_slices = EntryCreatingIterator::new(self, dir_path)?.skip(run_start);
+		for (entry, entry_slice) in entries.iter().zip(entry_slices) {
+			entry_slice?.copy_from_slice(entry);
+		}
+		Ok(())
 	}
 
 	/// Writes a `data` to disk at `path`
 	///
+	/// The changes may stay in the buffers until [`Self::sync`] is called.
+	///
 	/// # Safety
 	///
 	/// The dynamic allocator must be initialized.
@@ -737,10 +1463,11 @@ impl Driver {
 			Ok(f) => f,
 			Err(FatError::IsDirectory) => return Err(FatError::IsDirectory),
 			Err(FatError::PathNotFound) => self.create_empty_file(path)?,
-			_ => unreachable!(),
+			Err(e) => return Err(e),
 		};
-
-		println!("File info: {:#?}", file_info);
+		if file_info.is_read_only() {
+			return Err(FatError::ReadOnly);
+		}
 
 		let old_size = file_info.size;
 		let new_size = data.len();
@@ -750,10 +1477,25 @@ impl Driver {
 		let old_cluster_count = (old_size + bytes_per_cluster - 1) / bytes_per_cluster;
 		let new_cluster_count = (new_size + bytes_per_cluster - 1) / bytes_per_cluster;
 
+		if new_cluster_count == 0 {
+			// An empty file has no clusters at all
+			if file_info.first_cluster != 0 {
+				self.free_cluster_chain(file_info.first_cluster)?;
+			}
+			file_info.first_cluster = 0;
+			file_info.size = 0;
+			return self.update_file_info(path, file_info);
+		}
+
+		// Fast path: if the cluster chain already has the right length,
+		// the FAT doesn't need to be touched at all.
+		let chain_has_right_length =
+			file_info.first_cluster != 0 && new_cluster_count == old_cluster_count;
+
 		let old_cluster_count = if file_info.first_cluster == 0 {
 			let new_cluster = self
 				.fat
-				.find_empty_cluster(2)
+				.find_empty_cluster()
 				.ok_or(FatError::FileSystemFull)?;
 			self.fat.set_next_cluster(new_cluster, None).unwrap();
 
@@ -763,11 +1505,16 @@ impl Driver {
 			old_cluster_count
 		};
 
-		if new_cluster_count > old_cluster_count {
+		if chain_has_right_length {
+			// Go straight to writing the clusters
+		} else if new_cluster_count > old_cluster_count {
 			// Extend cluster chain
 
 			let mut current_cluster = file_info.first_cluster;
-			while let Some(next_cluster) = self.fat.get_next_cluster(current_cluster) {
+			let mut visited_clusters = 0;
+			while let Some(next_cluster) =
+				self.next_cluster_bounded(current_cluster, &mut visited_clusters)?
+			{
 				assert_ne!(next_cluster, 0);
 				current_cluster = next_cluster;
 			}
@@ -778,7 +1525,7 @@ impl Driver {
 			let mut traversed_clusters = vec![];
 
 			for i in 0..clusters_to_allocate {
-				let new_cluster = match self.fat.find_empty_cluster(2) {
+				let new_cluster = match self.fat.find_empty_cluster() {
 					Some(new_cluster) => new_cluster,
 					None => {
 						for cluster in traversed_clusters {
@@ -800,19 +1547,20 @@ impl Driver {
 			// Truncate cluster chain
 
 			let mut last_cluster = file_info.first_cluster;
-			for i in 0..new_cluster_count - 1 {
-				last_cluster = self.fat.get_next_cluster(last_cluster).unwrap();
+			let mut visited_clusters = 0;
+			for _ in 0..new_cluster_count - 1 {
+				last_cluster = self
+					.next_cluster_bounded(last_cluster, &mut visited_clusters)?
+					.ok_or(FatError::CorruptChain)?;
 			}
 			// last_cluster is now the last cluster in the new chain
-			let mut current_cluster = self.fat.get_next_cluster(last_cluster).unwrap();
+			let current_cluster = self
+				.next_cluster_bounded(last_cluster, &mut visited_clusters)?
+				.ok_or(FatError::CorruptChain)?;
 			self.fat.set_next_cluster(last_cluster, None).unwrap();
 			// current_cluster is the first cluster to be removed
 
-			while let Some(next_cluster) = self.fat.get_next_cluster(current_cluster) {
-				self.fat.set_cluster_empty(current_cluster).unwrap();
-				current_cluster = next_cluster;
-			}
-			self.fat.set_cluster_empty(current_cluster).unwrap();
+			self.free_cluster_chain(current_cluster)?;
 		}
 
 		// Write clusters
@@ -820,22 +1568,28 @@ impl Driver {
 
 		let first_data_sector = self.first_data_sector();
 		let mut written_cluster_count = 0;
+		let mut visited_clusters = 0;
 
 		loop {
 			let cluster_start_sector =
 				(current_cluster as usize - 2) * sectors_per_cluster + first_data_sector;
 
 			for sector_offset in 0..self.header.sectors_per_cluster {
-				self.load_sector(cluster_start_sector + sector_offset);
-
 				let byte_offset = (written_cluster_count * sectors_per_cluster + sector_offset) * 512;
 				let rest_size = new_size.saturating_sub(byte_offset).min(512);
-				if rest_size > 0 {
-					self.buffer[0..rest_size].copy_from_slice(&data[byte_offset..byte_offset + rest_size]);
+				if rest_size == 512 {
+					// The whole sector is overwritten, so there is no need to read it first
+					self.load_sector_for_overwrite(cluster_start_sector + sector_offset);
+				} else if rest_size > 0 {
+					self.load_sector(cluster_start_sector + sector_offset)?;
+				} else {
+					// Past the end of the data; leave the rest of the cluster as-is
+					break;
 				}
+				self.buffer[0..rest_size].copy_from_slice(&data[byte_offset..byte_offset + rest_size]);
 			}
 
-			match self.fat.get_next_cluster(current_cluster) {
+			match self.next_cluster_bounded(current_cluster, &mut visited_clusters)? {
 				Some(next_cluster) => current_cluster = next_cluster,
 				None => break,
 			}
@@ -843,34 +1597,256 @@ impl Driver {
 		}
 
 		file_info.size = new_size;
-		self.update_file_info(path, file_info).unwrap();
-
-		self.flush();
+		self.update_file_info(path, file_info)?;
 
 		Ok(())
 	}
 
-	unsafe fn update_file_info(
-		&mut self,
-		path: &[u8],
-		new_file_info: FileInfo,
-	) -> Result<(), FatError> {
-		// println!("Updating file info of '{:#?}'", core::str::from_utf8(path).unwrap());
-		assert!(self.get_entry_info(path).is_ok());
+	/// Removes the directory entry of the file at `path`, and frees all of its clusters.
+	///
+	/// Fails with `FatError::IsDirectory` if `path` is a directory,
+	/// and with `FatError::ReadOnly` if the file is read-only.
+	unsafe fn delete_file(&mut self, path: Path) -> Result<(), FatError> {
+		if self.get_entry_info(path)?.is_read_only() {
+			return Err(FatError::ReadOnly);
+		}
+		self.remove_entry(path, false)
+	}
 
-		let (mut dir_path, mut file_name) = path.split_last_2(&SEPARATOR_CHAR);
+	/// Sets or clears the read-only attribute of the file or directory at `path`.
+	///
+	/// Fails with `FatError::IsRootDirectory` if `path` is the root directory, which has no entry to change.
+	unsafe fn set_read_only(&mut self, path: Path, read_only: bool) -> Result<(), FatError> {
+		if normalize_path(path).len() == 0 {
+			return Err(FatError::IsRootDirectory);
+		}
+		let mut file_info = self.get_entry_info(path)?;
+		if read_only {
+			file_info.attributes |= ATTRIBUTE_READ_ONLY;
+		} else {
+			file_info.attributes &= !ATTRIBUTE_READ_ONLY;
+		}
+		self.update_file_info(path, file_info)
+	}
+
+	/// Removes the empty directory at `path`, and frees all of its clusters.
+	///
+	/// Fails with `FatError::DirectoryNotEmpty` if it contains anything but `.` and `..`,
+	/// and with `FatError::IsRootDirectory` if `path` is the root directory.
+	unsafe fn remove_directory(&mut self, path: Path) -> Result<(), FatError> {
+		if normalize_path(path).len() == 0 {
+			return Err(FatError::IsRootDirectory);
+		}
+		let info = self.get_directory_info(path)?;
+		// A first cluster of 0 would be read as the root directory, but means there are no clusters at all
+		if info.first_cluster != 0 {
+			let is_empty = self
+				.get_entries(path)?
+				.iter()
+				.all(|entry| entry.name == b"."[..] || entry.name == b".."[..]);
+			if !is_empty {
+				return Err(FatError::DirectoryNotEmpty);
+			}
+		}
+		self.remove_entry(path, true)
+	}
+
+	/// Marks the directory entry at `path` (and the LFN entries of its long name) as unused, and frees all of its clusters.
+	///
+	/// `directory` is whether the entry should be a directory,
+	/// if it isn't this fails with `FatError::IsDirectory` or `FatError::IsntDirectory`.
+	unsafe fn remove_entry(&mut self, path: Path, directory: bool) -> Result<(), FatError> {
+		let file_info = self.get_entry_info(path)?;
+		match (file_info.is_directory, directory) {
+			(true, false) => return Err(FatError::IsDirectory),
+			(false, true) => return Err(FatError::IsntDirectory),
+			_ => {}
+		}
+
+		self.unlink_entry(path, &file_info)?;
+
+		match file_info.first_cluster {
+			// An empty file has no clusters
+			0 => Ok(()),
+			first_cluster => self.free_cluster_chain(first_cluster),
+		}
+	}
+
+	/// Marks the directory entry of `file_info`, which is at `path`, and the LFN entries of its long name as unused.
+	///
+	/// The clusters of the entry are left as they are.
+	unsafe fn unlink_entry(&mut self, path: Path, file_info: &FileInfo) -> Result<(), FatError> {
+		let (mut dir_path, mut file_name) = split_path(path);
+		if file_name.len() == 0 {
+			core::mem::swap(&mut dir_path, &mut file_name);
+		}
+
+		let mut entry_index = None;
+		let mut long_name_entry_count = 0;
+		for (index, entry_slice) in EntryIterator::new(self, dir_path)?.enumerate() {
+			let entry_slice = entry_slice?;
+			let dir_entry: DirectoryEntry = entry_slice[..].try_into().unwrap();
+
+			match dir_entry {
+				DirectoryEntry::Standard {
+					file_name: ref name,
+					..
+				} if names_equal(name.get_slice(), file_info.name.get_slice()) => {
+					entry_index = Some(index);
+					break;
+				}
+				DirectoryEntry::LongFileName { .. } => long_name_entry_count += 1,
+				_ => long_name_entry_count = 0,
+			}
+		}
+		let entry_index = entry_index.ok_or(FatError::PathNotFound)?;
+
+		// The LFN entries may be in an earlier sector, so they are found again by index
+		let entry_slices = EntryIterator::new(self, dir_path)?
+			.skip(entry_index - long_name_entry_count)
+			.take(long_name_entry_count + 1);
+		for entry_slice in entry_slices {
+			// 0xE5 marks the entry as unused
+			entry_slice?[0] = 0xE5;
+		}
+		Ok(())
+	}
+
+	/// Renames the file or directory at `from` to `to`, which may be in another directory.
+	///
+	/// Only the directory entry is changed or moved, the data of the file stays where it is.
+	///
+	/// Fails with `FatError::AlreadyExists` if there already is something at `to`,
+	/// with `FatError::PathNotFound` if the directory `to` would be in doesn't exist,
+	/// with `FatError::MovedIntoItself` if `to` is inside the directory `from`,
+	/// and with `FatError::ReadOnly` if `from` is read-only.
+	unsafe fn rename(&mut self, from: Path, to: Path) -> Result<(), FatError> {
+		let from = &normalize_path(from)[..];
+		let to = &normalize_path(to)[..];
+		if from.len() == 0 {
+			return Err(FatError::IsRootDirectory);
+		}
+		let mut file_info = self.get_entry_info(from)?;
+		if file_info.is_read_only() {
+			return Err(FatError::ReadOnly);
+		}
+
+		let (mut from_dir, mut from_name) = split_path(from);
+		if from_name.len() == 0 {
+			core::mem::swap(&mut from_dir, &mut from_name);
+		}
+		let (mut to_dir, mut to_name) = split_path(to);
+		if to_name.len() == 0 {
+			core::mem::swap(&mut to_dir, &mut to_name);
+		}
+
+		if file_info.is_directory
+			&& to.len() > from.len()
+			&& names_equal(&to[..from.len()], from)
+			&& is_separator(to[from.len()])
+		{
+			return Err(FatError::MovedIntoItself);
+		}
+
+		let to_dir_info = self.get_directory_info(to_dir)?;
+		let same_directory =
+			self.get_directory_info(from_dir)?.first_cluster == to_dir_info.first_cluster;
+		match self.get_entry_info(to) {
+			// Only the case of the name changes
+			Ok(existing) if same_directory && existing.name == file_info.name => {}
+			Ok(_) => return Err(FatError::AlreadyExists),
+			Err(FatError::PathNotFound) => {}
+			Err(e) => return Err(e),
+		}
+
+		// Without long names the entry can simply be renamed where it is
+		if same_directory && file_info.long_name.is_none() {
+			if let Ok(short_name) = to_short_name(to_name) {
+				file_info.name = short_name;
+				return self.update_file_info(from, file_info);
+			}
+		}
+
+		let mut new_info = self.add_entry(
+			to_dir,
+			to_name,
+			file_info.attributes,
+			file_info.first_cluster,
+		)?;
+		new_info.size = file_info.size;
+		new_info.created = file_info.created;
+		new_info.modified = file_info.modified;
+		self.update_file_info(&new_info.path_in(to_dir), new_info.clone())?;
+		self.unlink_entry(from, &file_info)?;
+
+		if file_info.is_directory && !same_directory && file_info.first_cluster != 0 {
+			// The `..` entry of the directory has to point to its new parent
+			let parent_cluster = self.dot_dot_cluster(to_dir_info.first_cluster);
+			for entry_slice in EntryIterator::new(self, &new_info.path_in(to_dir))? {
+				let entry_slice = entry_slice?;
+				let dir_entry: DirectoryEntry = entry_slice[..].try_into().unwrap();
+				match dir_entry {
+					DirectoryEntry::Standard { file_name, .. } if file_name == b".."[..] => {
+						entry_slice[20..22].copy_from_slice(&((parent_cluster >> 16) as u16).to_le_bytes());
+						entry_slice[26..28].copy_from_slice(&(parent_cluster as u16).to_le_bytes());
+						break;
+					}
+					_ => continue,
+				}
+			}
+		}
+
+		Ok(())
+	}
+
+	/// The cluster stored in the `..` entry of a directory whose parent starts at `parent_cluster`.
+	///
+	/// This is the parent's first cluster, except for the root directory, which is always stored as 0.
+	fn dot_dot_cluster(&self, parent_cluster: u32) -> u32 {
+		match self.header.fat_version {
+			FatVersion::Fat32 {
+				root_dir_cluster, ..
+			} if parent_cluster == root_dir_cluster => 0,
+			_ => parent_cluster,
+		}
+	}
+
+	/// Marks every cluster in the chain starting at `first_cluster` as empty.
+	unsafe fn free_cluster_chain(&mut self, first_cluster: u32) -> Result<(), FatError> {
+		let mut current_cluster = first_cluster;
+		let mut visited_clusters = 0;
+		while let Some(next_cluster) =
+			self.next_cluster_bounded(current_cluster, &mut visited_clusters)?
+		{
+			self.fat.set_cluster_empty(current_cluster).unwrap();
+			current_cluster = next_cluster;
+		}
+		self.fat.set_cluster_empty(current_cluster).unwrap();
+		Ok(())
+	}
+
+	unsafe fn update_file_info(
+		&mut self,
+		path: &[u8],
+		new_file_info: FileInfo,
+	) -> Result<(), FatError> {
+		// println!("Updating file info of '{:#?}'", core::str::from_utf8(path).unwrap());
+		let (mut dir_path, mut file_name) = split_path(path);
 		if file_name.len() == 0 {
 			core::mem::swap(&mut dir_path, &mut file_name);
 		}
+		// The entry may have been found by its long name, but only the 8.3 name is in the standard entry
+		let short_name = self.get_entry_info(path)?.name;
 
 		for entry_slice in EntryIterator::new(self, dir_path)? {
+			let entry_slice = entry_slice?;
 			let dir_entry: DirectoryEntry = entry_slice[..].try_into().unwrap();
 
 			match dir_entry {
 				DirectoryEntry::Standard {
 					file_name: ref name,
 					..
-				} if name.get_slice() == file_name => {
+				} if names_equal(name.get_slice(), short_name.get_slice()) => {
 					let mut dir_entry = dir_entry;
 					dir_entry.update(new_file_info);
 					let new_file_info: [u8; 32] = dir_entry.into();
@@ -881,7 +1857,7 @@ impl Driver {
 			}
 		}
 
-		unreachable!()
+		Err(FatError::PathNotFound)
 	}
 
 	unsafe fn create_directory(&mut self, path: Path) -> Result<FileInfo, FatError> {
@@ -905,150 +1881,287 @@ impl Driver {
 			name: SVec::new(),
 			size: 0,
 			is_directory: true,
+			attributes: ATTRIBUTE_DIRECTORY,
+			long_name: None,
+			created: None,
+			modified: None,
 			first_cluster: parent_dir_cluster,
 		};
 
 		let mut separator_indices = path
 			.iter()
 			.enumerate()
-			.filter_map(|(i, c)| if *c == SEPARATOR_CHAR { Some(i) } else { None })
+			.filter_map(|(i, c)| if is_separator(*c) { Some(i) } else { None })
 			.collect::<Vec<_>>();
 		separator_indices.insert(0, 0);
 		separator_indices.push(path.len());
 		'path_parts_loop: for i in 0..separator_indices.len() - 1 {
 			let parent = &path[..separator_indices[i]];
 			let dir_to_create = &path[separator_indices[i] + 1.min(i)..separator_indices[i + 1]];
-			println!(
-				"Creating dir {} in {}",
-				core::str::from_utf8(dir_to_create).unwrap(),
-				core::str::from_utf8(parent).unwrap()
-			);
+			let existing_entries = self.get_entries(parent)?;
+			if let Some(entry) = existing_entries
+				.into_iter()
+				.find(|entry| entry.has_name(dir_to_create))
+			{
+				if i == separator_indices.len() - 2 {
+					return Err(FatError::AlreadyExists);
+				}
+				if !entry.is_directory {
+					return Err(FatError::IsntDirectory);
+				}
+				parent_dir_cluster = entry.first_cluster;
+				latest_file_info = entry;
+				continue 'path_parts_loop;
+			}
 
-			for entry_slice in EntryIterator::new(self, parent)? {
-				let dir_entry: DirectoryEntry = entry_slice[..].try_into().unwrap();
+			let dir_cluster = self
+				.fat
+				.find_empty_cluster()
+				.ok_or(FatError::FileSystemFull)?;
+			self.fat.set_next_cluster(dir_cluster, None).unwrap();
+			let cluster_sector =
+				(dir_cluster as usize - 2) * self.header.sectors_per_cluster + self.first_data_sector();
 
-				match dir_entry {
-					DirectoryEntry::Standard {
-						file_name,
-						first_cluster,
-						..
-					} if file_name.get_slice() == dir_to_create => {
-						println!(
-							"Found existing dir {}",
-							core::str::from_utf8(dir_to_create).unwrap()
-						);
-						parent_dir_cluster = first_cluster;
-						latest_file_info = FileInfo {
-							name: file_name,
-							size: 0,
-							is_directory: true,
-							first_cluster,
-						};
-						continue 'path_parts_loop;
-					}
-					_ => continue,
-				}
+			for sector_offset in 0..self.header.sectors_per_cluster {
+				self.load_sector(cluster_sector + sector_offset)?;
+				self.buffer = core::mem::zeroed();
 			}
 
-			for entry_slice in EntryCreatingIterator::new(self, parent)? {
-				let dir_entry: DirectoryEntry = entry_slice[..].try_into().unwrap();
+			self.load_sector(cluster_sector)?;
 
-				match dir_entry {
-					DirectoryEntry::Empty | DirectoryEntry::Unused => {
-						println!(
-							"Creating dir {}",
-							core::str::from_utf8(dir_to_create).unwrap()
-						);
-						let entry_slice_ptr = entry_slice.as_mut_ptr();
-						let old_sector = self.current_loaded_sector;
-
-						let dir_cluster = self
-							.fat
-							.find_empty_cluster(2)
-							.ok_or(FatError::FileSystemFull)?;
-						self.fat.set_next_cluster(dir_cluster, None).unwrap();
-						let cluster_sector = (dir_cluster as usize - 2) * self.header.sectors_per_cluster
-							+ self.first_data_sector();
-
-						for sector_offset in 0..self.header.sectors_per_cluster {
-							self.load_sector(cluster_sector + sector_offset);
-							self.buffer = core::mem::zeroed();
-						}
+			let current_directory_entry = DirectoryEntry::Standard {
+				file_name: SVec::try_from(&b"."[..]).unwrap(),
+				attributes: 0x10, // directory
+				first_cluster: dir_cluster,
+				file_size: 0,
+				created: None,
+				modified: None,
+			};
 
-						self.load_sector(cluster_sector);
-
-						let current_directory_entry = DirectoryEntry::Standard {
-							file_name: {
-								let mut name = SVec::new();
-								name.push(b'.');
-								name.push(b'.'); // lol lmao
-								name
-							},
-							attributes: 0x10, // directory
-							first_cluster: dir_cluster,
-							file_size: 0,
-						};
+			let parent_directory_entry = DirectoryEntry::Standard {
+				file_name: SVec::try_from(&b".."[..]).unwrap(),
+				attributes: 0x10, // Directory
+				first_cluster: self.dot_dot_cluster(parent_dir_cluster),
+				file_size: 0,
+				created: None,
+				modified: None,
+			};
 
-						let parent_directory_entry = DirectoryEntry::Standard {
-							file_name: {
-								let mut name = SVec::new();
-								name.push(b'.');
-								name.push(b'.');
-								name.push(b'.');
-								name
-							},
-							attributes: 0x10, // Directory
-							first_cluster: parent_dir_cluster,
-							file_size: 0,
-						};
+			let temp: [u8; 32] = current_directory_entry.into();
+			self.buffer[0..32].copy_from_slice(&temp[..]);
 
-						let temp: [u8; 32] = current_directory_entry.into();
-						self.buffer[0..32].copy_from_slice(&temp[..]);
+			let temp: [u8; 32] = parent_directory_entry.into();
+			self.buffer[32..64].copy_from_slice(&temp[..]);
 
-						let temp: [u8; 32] = parent_directory_entry.into();
-						self.buffer[32..64].copy_from_slice(&temp[..]);
+			latest_file_info = self.add_entry(parent, dir_to_create, ATTRIBUTE_DIRECTORY, dir_cluster)?;
+			parent_dir_cluster = dir_cluster;
+		}
 
-						println!("buffer: {:x?}", self.buffer);
+		Ok(latest_file_info)
+	}
 
-						self.load_sector(old_sector);
+	/// Finds the directory entry of the file at `path`.
+	///
+	/// Returns the file's info, the sector containing the entry,
+	/// and the byte offset of the entry inside that sector.
+	unsafe fn find_entry_location(
+		&mut self,
+		path: Path,
+	) -> Result<(FileInfo, usize, usize), FatError> {
+		let file_info = self.get_file_info(path)?;
 
-						let dir_name = {
-							let mut name = SVec::new();
-							for b in dir_to_create {
-								name.push(*b);
-							}
-							name
-						};
+		let (mut dir_path, mut file_name) = split_path(path);
+		if file_name.len() == 0 {
+			core::mem::swap(&mut dir_path, &mut file_name);
+		}
 
-						let dir_to_create_entry = DirectoryEntry::Standard {
-							file_name: dir_name.clone(),
-							attributes: 0x10, // directory
-							first_cluster: dir_cluster,
-							file_size: 0,
-						};
+		let mut entries = EntryIterator::new(self, dir_path)?;
+		while let Some(entry_slice) = entries.next() {
+			let entry_slice = entry_slice?;
+			let dir_entry: DirectoryEntry = entry_slice[..].try_into().unwrap();
+
+			match dir_entry {
+				DirectoryEntry::Standard {
+					file_name: ref name,
+					..
+				} if names_equal(name.get_slice(), file_info.name.get_slice()) => {
+					let sector = entries.inner.current_loaded_sector;
+					let offset = entry_slice.as_ptr() as usize - entries.inner.buffer.as_ptr() as usize;
+					return Ok((file_info, sector, offset));
+				}
+				_ => continue,
+			}
+		}
+
+		Err(FatError::PathNotFound)
+	}
+
+	/// Reads the data of the file described by `file_info`, starting at byte `offset`, into `buffer`.
+	///
+	/// Returns the number of bytes read, which is 0 if `offset` is at or past the end of the file.
+	/// Fails with `FatError::CorruptChain` if the cluster chain ends before the file does, or loops.
+	unsafe fn read_file_at(
+		&mut self,
+		file_info: &FileInfo,
+		offset: usize,
+		buffer: &mut [u8],
+	) -> Result<usize, FatError> {
+		if offset >= file_info.size || file_info.first_cluster == 0 {
+			return Ok(0);
+		}
 
-						let entry_slice = core::slice::from_raw_parts_mut(entry_slice_ptr, 32);
+		let bytes_per_cluster = self.header.sectors_per_cluster * 512;
+		let to_read = buffer.len().min(file_info.size - offset);
 
-						let temp: [u8; 32] = dir_to_create_entry.into();
-						entry_slice.copy_from_slice(&temp[..]);
+		let mut visited_clusters = 0;
+		let mut current_cluster = file_info.first_cluster;
+		for _ in 0..offset / bytes_per_cluster {
+			current_cluster = self
+				.next_cluster_bounded(current_cluster, &mut visited_clusters)?
+				.ok_or(FatError::CorruptChain)?;
+		}
 
-						parent_dir_cluster = dir_cluster;
+		let mut read = 0;
+		while read < to_read {
+			let position = offset + read;
+			let sector = self.cluster_start_sector(current_cluster) + position % bytes_per_cluster / 512;
+			let offset_in_sector = position % 512;
+			let count = (512 - offset_in_sector).min(to_read - read);
+
+			self.load_sector(sector)?;
+			buffer[read..read + count]
+				.copy_from_slice(&self.buffer[offset_in_sector..offset_in_sector + count]);
+			read += count;
+
+			if read < to_read && (offset + read) % bytes_per_cluster == 0 {
+				current_cluster = self
+					.next_cluster_bounded(current_cluster, &mut visited_clusters)?
+					.ok_or(FatError::CorruptChain)?;
+			}
+		}
 
-						latest_file_info = FileInfo {
-							name: dir_name,
-							size: 0,
-							is_directory: true,
-							first_cluster: dir_cluster,
-						};
+		Ok(read)
+	}
 
-						continue 'path_parts_loop;
-					}
-					_ => continue,
-				}
+	/// Writes `data` to the file described by `file_info`, starting at byte `offset`.
+	///
+	/// The cluster chain is extended as needed, and `file_info` is updated
+	/// with the new first cluster and size. The directory entry is *not* updated.
+	///
+	/// Returns the number of bytes written.
+	unsafe fn write_file_at(
+		&mut self,
+		file_info: &mut FileInfo,
+		offset: usize,
+		data: &[u8],
+	) -> Result<usize, FatError> {
+		if offset > file_info.size {
+			return Err(FatError::InvalidOffset);
+		}
+		if data.len() == 0 {
+			return Ok(0);
+		}
+
+		let bytes_per_cluster = self.header.sectors_per_cluster * 512;
+		let end = offset + data.len();
+		let needed_cluster_count = (end + bytes_per_cluster - 1) / bytes_per_cluster;
+
+		if file_info.first_cluster == 0 {
+			let new_cluster = self
+				.fat
+				.find_empty_cluster()
+				.ok_or(FatError::FileSystemFull)?;
+			self.fat.set_next_cluster(new_cluster, None).unwrap();
+			file_info.first_cluster = new_cluster;
+		}
+
+		// Extend the cluster chain if it is too short
+		let mut last_cluster = file_info.first_cluster;
+		let mut cluster_count = 1;
+		let mut visited_clusters = 0;
+		while let Some(next_cluster) = self.next_cluster_bounded(last_cluster, &mut visited_clusters)? {
+			last_cluster = next_cluster;
+			cluster_count += 1;
+		}
+		while cluster_count < needed_cluster_count {
+			let new_cluster = self
+				.fat
+				.find_empty_cluster()
+				.ok_or(FatError::FileSystemFull)?;
+			self
+				.fat
+				.set_next_cluster(last_cluster, Some(new_cluster))
+				.unwrap();
+			self.fat.set_next_cluster(new_cluster, None).unwrap();
+			last_cluster = new_cluster;
+			cluster_count += 1;
+		}
+
+		let mut visited_clusters = 0;
+		let mut current_cluster = file_info.first_cluster;
+		for _ in 0..offset / bytes_per_cluster {
+			current_cluster = self
+				.next_cluster_bounded(current_cluster, &mut visited_clusters)?
+				.ok_or(FatError::CorruptChain)?;
+		}
+
+		let mut written = 0;
+		while written < data.len() {
+			let position = offset + written;
+			let sector = self.cluster_start_sector(current_cluster) + position % bytes_per_cluster / 512;
+			let offset_in_sector = position % 512;
+			let count = (512 - offset_in_sector).min(data.len() - written);
+
+			if count == 512 {
+				self.load_sector_for_overwrite(sector);
+			} else {
+				self.load_sector(sector)?;
+			}
+			self.buffer[offset_in_sector..offset_in_sector + count]
+				.copy_from_slice(&data[written..written + count]);
+			written += count;
+
+			if written < data.len() && (offset + written) % bytes_per_cluster == 0 {
+				current_cluster = self
+					.next_cluster_bounded(current_cluster, &mut visited_clusters)?
+					.ok_or(FatError::CorruptChain)?;
 			}
 		}
 
-		Ok(latest_file_info)
+		file_info.size = file_info.size.max(end);
+
+		Ok(written)
+	}
+
+	/// Overwrites the directory entry at `offset` in `sector` with `file_info`
+	unsafe fn write_entry_at(
+		&mut self,
+		sector: usize,
+		offset: usize,
+		file_info: FileInfo,
+	) -> Result<(), FatError> {
+		self.load_sector(sector)?;
+		let mut dir_entry: DirectoryEntry = self.buffer[offset..offset + 32].try_into().unwrap();
+		dir_entry.update(file_info);
+		let new_entry: [u8; 32] = dir_entry.into();
+		self.buffer[offset..offset + 32].copy_from_slice(&new_entry);
+		Ok(())
+	}
+
+	/// The sector, and the byte offset in that sector, of entry number `index` in the FAT12/16 root directory
+	fn root_dir_entry_location(&self, index: usize) -> (usize, usize) {
+		let first_root_dir_sector =
+			self.header.reserved_sectors + self.header.fat_count * self.header.sectors_per_fat;
+		let entries_per_sector = 512 / 32;
+		(
+			first_root_dir_sector + index / entries_per_sector,
+			index % entries_per_sector * 32,
+		)
+	}
+
+	/// The first sector of `cluster`
+	fn cluster_start_sector(&mut self, cluster: u32) -> usize {
+		(cluster as usize - 2) * self.header.sectors_per_cluster + self.first_data_sector()
 	}
 
 	fn first_data_sector(&mut self) -> usize {
@@ -1059,12 +2172,133 @@ impl Driver {
 		first_data_sector
 	}
 
-	/// Writes the buffer to disk
+	/// The number of clusters in the data region.
+	///
+	/// No valid cluster chain can be longer than this.
+	fn data_cluster_count(&mut self) -> usize {
+		(self.header.total_sectors - self.first_data_sector()) / self.header.sectors_per_cluster
+	}
+
+	/// The number of free bytes, and the total number of bytes, in the data region.
+	///
+	/// On FAT32 the free cluster count of the FSInfo sector is used if it is known,
+	/// otherwise the free clusters are counted in the FAT.
+	unsafe fn free_space(&mut self) -> (usize, usize) {
+		let cluster_count = self.data_cluster_count();
+		let free_clusters = self.free_cluster_count() as usize;
+		let cluster_size = self.header.sectors_per_cluster * 512;
+		(free_clusters * cluster_size, cluster_count * cluster_size)
+	}
+
+	/// The number of free clusters, which is counted in the FAT if it isn't known
+	fn free_cluster_count(&mut self) -> u32 {
+		if let Some(free_count) = self.fat.free_count {
+			return free_count;
+		}
+		let cluster_count = self.data_cluster_count() as u32;
+		// Data clusters are numbered from 2
+		let free_count = (2..cluster_count + 2)
+			.filter(|&cluster| self.fat.get_next_cluster(cluster) == Some(0))
+			.count() as u32;
+		self.fat.free_count = Some(free_count);
+		free_count
+	}
+
+	/// Reads the FSInfo sector, if this is FAT32 and it is valid
+	unsafe fn read_fsinfo(&mut self) -> Result<Option<FsInfo>, FatError> {
+		match self.header.fat_version {
+			FatVersion::Fat32 { fsinfo_sector, .. } => {
+				self.load_sector(fsinfo_sector)?;
+				Ok(FsInfo::read(&self.buffer))
+			}
+			_ => Ok(None),
+		}
+	}
+
+	/// Writes the free cluster count and the next free cluster hint to the FSInfo sector,
+	/// if this is FAT32 and it is valid
+	unsafe fn write_fsinfo(&mut self) -> Result<(), FatError> {
+		// Counting the free clusters may scan the whole FAT, so it is only done if there is somewhere to put the count
+		if self.read_fsinfo()?.is_none() {
+			return Ok(());
+		}
+		// Only the FAT is read while counting, so the FSInfo sector stays loaded
+		let fsinfo = FsInfo {
+			free_count: Some(self.free_cluster_count()),
+			next_free: Some(self.fat.next_free_hint),
+		};
+		fsinfo.write(&mut self.buffer);
+		Ok(())
+	}
+
+	/// The clusters of the file or directory at `path`, in order.
+	///
+	/// Fails with `FatError::CorruptChain` if the cluster chain is longer than
+	/// the number of clusters, which means it loops.
+	unsafe fn file_layout(&mut self, path: Path) -> Result<Vec<u32>, FatError> {
+		let file_info = self.get_entry_info(path)?;
+		let mut clusters = Vec::new();
+		if file_info.first_cluster == 0 {
+			return Ok(clusters);
+		}
+
+		let mut visited_clusters = 0;
+		let mut cluster = Some(file_info.first_cluster);
+		while let Some(current_cluster) = cluster {
+			clusters.push(current_cluster);
+			cluster = self.next_cluster_bounded(current_cluster, &mut visited_clusters)?;
+		}
+		Ok(clusters)
+	}
+
+	/// Follows the cluster chain starting at `first_cluster` to its end.
+	///
+	/// Fails with `FatError::CorruptChain` if it loops, so code which follows it afterwards
+	/// without counting the clusters knows that it ends.
+	fn check_chain(&mut self, first_cluster: u32) -> Result<(), FatError> {
+		let mut visited_clusters = 0;
+		let mut cluster = Some(first_cluster);
+		while let Some(current_cluster) = cluster {
+			cluster = self.next_cluster_bounded(current_cluster, &mut visited_clusters)?;
+		}
+		Ok(())
+	}
+
+	/// Like [`FileAllocationTable::get_next_cluster`], but counts the clusters visited in `visited_clusters`.
+	///
+	/// Fails with `FatError::CorruptChain` if the chain gets longer than the number of clusters,
+	/// as that means it loops, which would otherwise hang whoever follows it.
+	fn next_cluster_bounded(
+		&mut self,
+		cluster: u32,
+		visited_clusters: &mut usize,
+	) -> Result<Option<u32>, FatError> {
+		*visited_clusters += 1;
+		if *visited_clusters > self.data_cluster_count() {
+			return Err(FatError::CorruptChain);
+		}
+		Ok(self.fat.get_next_cluster(cluster))
+	}
+
+	/// Puts the buffer in `dirty_sectors` if it has been changed
 	fn flush(&mut self) {
-		unsafe {
-			super::partitions::write_sectors(0, self.current_loaded_sector, &self.buffer);
+		if self.stored_buffer != Some(self.buffer) {
+			self
+				.dirty_sectors
+				.insert(self.current_loaded_sector, &self.buffer);
+			self.stored_buffer = Some(self.buffer);
 		}
 	}
+
+	/// Writes all changed sectors, both of the FAT and the rest of the file system, to disk
+	///
+	/// The FSInfo sector is updated first, so it matches the FAT.
+	fn sync(&mut self) -> Result<(), FatError> {
+		unsafe { self.write_fsinfo()? };
+		self.flush();
+		unsafe { self.dirty_sectors.write_all(self.partition) }.map_err(FatError::Disk)?;
+		self.fat.sync().map_err(FatError::Disk)
+	}
 }
 
 /// USING THIS MAY CAUSE UNDEFINED BEHAVIOUR
@@ -1079,10 +2313,15 @@ struct EntryCreatingIterator<'a> {
 }
 
 impl<'a> EntryCreatingIterator<'a> {
+	/// Fails with `FatError::CorruptChain` if the cluster chain of the directory loops,
+	/// as iterating through it would never end.
 	unsafe fn new(driver: &'a mut Driver, path: Path) -> Result<Self, FatError> {
 		let dir_info = driver.get_directory_info(path)?;
 
 		let is_root_directory = dir_info.first_cluster == 0;
+		if !is_root_directory {
+			driver.check_chain(dir_info.first_cluster)?;
+		}
 
 		Ok(Self {
 			inner: driver,
@@ -1098,7 +2337,8 @@ impl<'a> EntryCreatingIterator<'a> {
 }
 
 impl<'a> Iterator for EntryCreatingIterator<'a> {
-	type Item = &'a mut [u8; 32];
+	/// Fails with `FatError::Disk` if a sector of the directory can't be read, which ends the iteration
+	type Item = Result<&'a mut [u8; 32], FatError>;
 
 	fn next(&mut self) -> Option<Self::Item> {
 		unsafe {
@@ -1109,24 +2349,18 @@ impl<'a> Iterator for EntryCreatingIterator<'a> {
 					return None;
 				}
 
-				let root_dir_sectors = (self.inner.header.root_dir_entries * 32 + 511) / 512;
-				let first_root_dir_sector = self.inner.header.reserved_sectors
-					+ self.inner.header.fat_count * self.inner.header.sectors_per_fat;
-
-				let sector_offset = self.next_entry_index / (512 / 32);
-
-				self
-					.inner
-					.load_sector(first_root_dir_sector + sector_offset);
-
-				let entry_offset = self.next_entry_index % 32;
+				let (sector, byte_offset) = self.inner.root_dir_entry_location(self.next_entry_index);
+				if let Err(e) = self.inner.load_sector(sector) {
+					self.next_entry_index = self.inner.header.root_dir_entries;
+					return Some(Err(e));
+				}
 
-				let entry = &mut self.inner.buffer[entry_offset * 32..(entry_offset + 1) * 32];
+				let entry = &mut self.inner.buffer[byte_offset..byte_offset + 32];
 				let dir_entry: DirectoryEntry = entry.try_into().unwrap();
 				match dir_entry {
 					_ => {
 						self.next_entry_index += 1;
-						return Some(entry.as_mut_ptr().cast::<[u8; 32]>().as_mut().unwrap());
+						return Some(Ok(entry.as_mut_ptr().cast::<[u8; 32]>().as_mut().unwrap()));
 					}
 				}
 			} else if let Some(current_cluster) = self.next_cluster {
@@ -1146,11 +2380,14 @@ impl<'a> Iterator for EntryCreatingIterator<'a> {
 				let sector = cluster_sector + sector_offset;
 
 				// println!("Starting sector {}", sector);
-				self.inner.load_sector(sector);
+				if let Err(e) = self.inner.load_sector(sector) {
+					self.next_cluster = None;
+					return Some(Err(e));
+				}
 
 				let entry_offset = self.next_entry_index % (512 / 32);
 
-				let entry = &mut self.inner.buffer[entry_offset * 32..(entry_offset + 1) * 32];
+				let entry = entry_in_sector(&mut self.inner.buffer, entry_offset);
 				let dir_entry: DirectoryEntry = entry.try_into().unwrap();
 
 				match dir_entry {
@@ -1164,13 +2401,15 @@ impl<'a> Iterator for EntryCreatingIterator<'a> {
 								cluster @ Some(_) => cluster,
 								None => {
 									println!("Allocating new cluster");
-									let new_cluster = self.inner.fat.find_empty_cluster(2)?;
+									let new_cluster = self.inner.fat.find_empty_cluster()?;
 									for sector_offset in 0..self.inner.header.sectors_per_cluster {
 										let cluster_sector = (new_cluster as usize - 2)
 											* self.inner.header.sectors_per_cluster
 											+ first_data_sector;
 
-										self.inner.load_sector(cluster_sector + sector_offset);
+										self
+											.inner
+											.load_sector_for_overwrite(cluster_sector + sector_offset);
 										self.inner.buffer = core::mem::zeroed();
 									}
 									self
@@ -1183,11 +2422,14 @@ impl<'a> Iterator for EntryCreatingIterator<'a> {
 								}
 							};
 
-							self.inner.load_sector(sector);
-							let entry = &mut self.inner.buffer[entry_offset * 32..(entry_offset + 1) * 32];
-							return Some(entry.as_mut_ptr().cast::<[u8; 32]>().as_mut().unwrap());
+							if let Err(e) = self.inner.load_sector(sector) {
+								self.next_cluster = None;
+								return Some(Err(e));
+							}
+							let entry = entry_in_sector(&mut self.inner.buffer, entry_offset);
+							return Some(Ok(entry.as_mut_ptr().cast::<[u8; 32]>().as_mut().unwrap()));
 						} else {
-							return Some(entry.as_mut_ptr().cast::<[u8; 32]>().as_mut().unwrap());
+							return Some(Ok(entry.as_mut_ptr().cast::<[u8; 32]>().as_mut().unwrap()));
 						}
 					}
 				}
@@ -1210,10 +2452,15 @@ struct EntryIterator<'a> {
 }
 
 impl<'a> EntryIterator<'a> {
+	/// Fails with `FatError::CorruptChain` if the cluster chain of the directory loops,
+	/// as iterating through it would never end.
 	unsafe fn new(driver: &'a mut Driver, path: Path) -> Result<Self, FatError> {
 		let dir_info = driver.get_directory_info(path)?;
 
 		let is_root_directory = dir_info.first_cluster == 0;
+		if !is_root_directory {
+			driver.check_chain(dir_info.first_cluster)?;
+		}
 
 		Ok(Self {
 			inner: driver,
@@ -1229,7 +2476,8 @@ impl<'a> EntryIterator<'a> {
 }
 
 impl<'a> Iterator for EntryIterator<'a> {
-	type Item = &'a mut [u8; 32];
+	/// Fails with `FatError::Disk` if a sector of the directory can't be read, which ends the iteration
+	type Item = Result<&'a mut [u8; 32], FatError>;
 
 	fn next(&mut self) -> Option<Self::Item> {
 		unsafe {
@@ -1240,25 +2488,19 @@ impl<'a> Iterator for EntryIterator<'a> {
 					return None;
 				}
 
-				let root_dir_sectors = (self.inner.header.root_dir_entries * 32 + 511) / 512;
-				let first_root_dir_sector = self.inner.header.reserved_sectors
-					+ self.inner.header.fat_count * self.inner.header.sectors_per_fat;
-
-				let sector_offset = self.next_entry_index / (512 / 32);
-
-				self
-					.inner
-					.load_sector(first_root_dir_sector + sector_offset);
-
-				let entry_offset = self.next_entry_index % 32;
+				let (sector, byte_offset) = self.inner.root_dir_entry_location(self.next_entry_index);
+				if let Err(e) = self.inner.load_sector(sector) {
+					self.next_entry_index = self.inner.header.root_dir_entries;
+					return Some(Err(e));
+				}
 
-				let entry = &mut self.inner.buffer[entry_offset * 32..(entry_offset + 1) * 32];
+				let entry = &mut self.inner.buffer[byte_offset..byte_offset + 32];
 				let dir_entry: DirectoryEntry = entry.try_into().unwrap();
 				match dir_entry {
 					DirectoryEntry::Empty => return None,
 					_ => {
 						self.next_entry_index += 1;
-						return Some(entry.as_mut_ptr().cast::<[u8; 32]>().as_mut().unwrap());
+						return Some(Ok(entry.as_mut_ptr().cast::<[u8; 32]>().as_mut().unwrap()));
 					}
 				}
 			} else if let Some(current_cluster) = self.next_cluster {
@@ -1278,11 +2520,14 @@ impl<'a> Iterator for EntryIterator<'a> {
 				let sector = cluster_sector + sector_offset;
 
 				// println!("Starting sector {}", sector);
-				self.inner.load_sector(sector);
+				if let Err(e) = self.inner.load_sector(sector) {
+					self.next_cluster = None;
+					return Some(Err(e));
+				}
 
 				let entry_offset = self.next_entry_index % (512 / 32);
 
-				let entry = &mut self.inner.buffer[entry_offset * 32..(entry_offset + 1) * 32];
+				let entry = entry_in_sector(&mut self.inner.buffer, entry_offset);
 				let dir_entry: DirectoryEntry = entry.try_into().unwrap();
 
 				match dir_entry {
@@ -1296,7 +2541,7 @@ impl<'a> Iterator for EntryIterator<'a> {
 							self.next_cluster = self.inner.fat.get_next_cluster(current_cluster);
 						}
 
-						return Some(entry.as_mut_ptr().cast::<[u8; 32]>().as_mut().unwrap());
+						return Some(Ok(entry.as_mut_ptr().cast::<[u8; 32]>().as_mut().unwrap()));
 					}
 				}
 			} else {
@@ -1314,18 +2559,126 @@ pub enum FatError {
 	/// How big the file is
 	BufferTooSmall(usize),
 	FileSystemFull,
+	/// An offset past the end of a file was used
+	InvalidOffset,
+	/// Attempted to create a file where there already is one
+	AlreadyExists,
+	/// A cluster chain loops, or ends before the file it belongs to does
+	CorruptChain,
+	/// A file name isn't a valid 8.3 name or long file name
+	InvalidName,
+	/// Attempted to modify a file with the read-only attribute set
+	ReadOnly,
+	/// Attempted to remove a directory which still contains files or directories
+	DirectoryNotEmpty,
+	/// Attempted to remove or rename the root directory
+	IsRootDirectory,
+	/// Attempted to move a directory into itself
+	MovedIntoItself,
+	/// The disk couldn't be read from or written to
+	Disk(PataError),
+	/// The partition doesn't exist, can't be read, or doesn't contain a FAT file system
+	NoFileSystem,
 }
 
-enum DirectoryEntry {
-	Standard {
-		file_name: SVec<u8, 12>,
-		attributes: u8,
-		first_cluster: u32,
-		file_size: u32,
-	},
-	LongFileName {},
-	Unused,
-	Empty,
+/// Puts together a long file name (LFN) from the LFN entries preceding a standard entry.
+///
+/// Each LFN entry holds 13 UCS-2 characters of the name. The entries are stored last part first,
+/// the last part having bit 0x40 set in its order, and each has the checksum of the 8.3 name of
+/// the standard entry the name belongs to. Parts which are out of order, or don't belong to
+/// the standard entry following them, are ignored.
+struct LongNameBuilder {
+	chars: [u16; 13 * 20],
+	/// The order of the next expected entry, 0 when the name is complete
+	next_order: u8,
+	/// The number of entries the name is made of
+	entry_count: u8,
+	checksum: u8,
+	/// If the entries seen since the last standard entry form a (partial) long name
+	valid: bool,
+}
+
+impl LongNameBuilder {
+	fn new() -> Self {
+		Self {
+			chars: [0; 13 * 20],
+			next_order: 0,
+			entry_count: 0,
+			checksum: 0,
+			valid: false,
+		}
+	}
+
+	/// Adds the characters of an LFN entry
+	fn add_entry(&mut self, order: u8, checksum: u8, chars: &[u16; 13]) {
+		let index = order & 0x1F;
+		if order & 0x40 != 0 {
+			// A new name starts, throwing away any unfinished one
+			self.valid = (1..=20).contains(&index);
+			self.entry_count = index;
+			self.checksum = checksum;
+		} else if index != self.next_order || checksum != self.checksum {
+			self.valid = false;
+		}
+		if self.valid {
+			let start = (index as usize - 1) * 13;
+			self.chars[start..start + 13].copy_from_slice(chars);
+			self.next_order = index - 1;
+		}
+	}
+
+	/// Throws away any unfinished name
+	fn reset(&mut self) {
+		self.valid = false;
+	}
+
+	/// Returns the long name belonging to the standard entry whose raw 8.3 name is `short_name`, if there is one.
+	///
+	/// Also resets the builder for the next entry.
+	fn finish(&mut self, short_name: &[u8]) -> Option<String> {
+		let is_complete = self.valid && self.next_order == 0;
+		self.valid = false;
+		if !is_complete || self.checksum != short_name_checksum(short_name) {
+			return None;
+		}
+		// The name ends with a 0 if it doesn't fill the last entry
+		let chars = self.chars[..self.entry_count as usize * 13]
+			.iter()
+			.copied()
+			.take_while(|&c| c != 0);
+		Some(
+			core::char::decode_utf16(chars)
+				.map(|c| c.unwrap_or(core::char::REPLACEMENT_CHARACTER))
+				.collect(),
+		)
+	}
+}
+
+/// The checksum of a raw 8.3 name (11 bytes, space padded, without the `.`), which LFN entries refer to
+fn short_name_checksum(short_name: &[u8]) -> u8 {
+	short_name
+		.iter()
+		.fold(0u8, |sum, &b| sum.rotate_right(1).wrapping_add(b))
+}
+
+enum DirectoryEntry {
+	Standard {
+		file_name: SVec<u8, 12>,
+		attributes: u8,
+		first_cluster: u32,
+		file_size: u32,
+		created: Option<FatTimestamp>,
+		modified: Option<FatTimestamp>,
+	},
+	LongFileName {
+		/// The index of this entry in the name, starting at 1. Bit 0x40 is set for the last entry.
+		order: u8,
+		/// The checksum of the 8.3 name of the entry the long name belongs to
+		checksum: u8,
+		chars: [u16; 13],
+	},
+	Unused,
+	Empty,
 }
 
 impl DirectoryEntry {
@@ -1336,12 +2689,17 @@ impl DirectoryEntry {
 				attributes,
 				first_cluster,
 				file_size,
+				created,
+				modified,
 			} => {
 				*file_name = file_info.name;
 				// Set or clear directory flag (0x10) depending on file_info.is_directory
-				*attributes = if file_info.is_directory { 0x10 } else { 0x00 } | (*attributes & !0x10);
+				*attributes =
+					if file_info.is_directory { 0x10 } else { 0x00 } | (file_info.attributes & !0x10);
 				*first_cluster = file_info.first_cluster;
 				*file_size = file_info.size as _;
+				*created = file_info.created;
+				*modified = file_info.modified;
 			}
 			_ => unimplemented!(),
 		}
@@ -1364,7 +2722,20 @@ impl TryFrom<&[u8]> for DirectoryEntry {
 
 		let attributes = value[11];
 		if attributes == 0x0F {
-			return Ok(Self::LongFileName {});
+			// The 13 UCS-2 characters are spread out over three parts of the entry
+			let mut chars = [0; 13];
+			let char_bytes = value[1..11]
+				.chunks(2)
+				.chain(value[14..26].chunks(2))
+				.chain(value[28..32].chunks(2));
+			for (c, bytes) in chars.iter_mut().zip(char_bytes) {
+				*c = u16::from_le_bytes([bytes[0], bytes[1]]);
+			}
+			return Ok(Self::LongFileName {
+				order: value[0],
+				checksum: value[13],
+				chars,
+			});
 		}
 
 		let mut bare_name: SVec<_, 8> = SVec::new();
@@ -1409,6 +2780,8 @@ impl TryFrom<&[u8]> for DirectoryEntry {
 			attributes,
 			first_cluster: cluster,
 			file_size,
+			created: FatTimestamp::read(value, 0x0E),
+			modified: FatTimestamp::read(value, 0x16),
 		})
 	}
 }
@@ -1429,12 +2802,19 @@ impl Into<[u8; 32]> for DirectoryEntry {
 				attributes,
 				first_cluster,
 				file_size,
+				created,
+				modified,
 			} => {
 				let mut ret = [0; 32];
 
 				let mut name = SVec::<u8, 8>::new();
 				let mut ext = SVec::<u8, 3>::new();
-				let (bare_name, extension) = file_name.get_slice().split_last_2(&b'.');
+				// The names of the `.` and `..` entries are all dots, and have no extension
+				let (bare_name, extension) = if file_name == b"."[..] || file_name == b".."[..] {
+					(file_name.get_slice(), &[][..])
+				} else {
+					file_name.get_slice().split_last_2(&b'.')
+				};
 				for &b in bare_name {
 					name.push(b);
 				}
@@ -1451,12 +2831,34 @@ impl Into<[u8; 32]> for DirectoryEntry {
 				ret[0..8].copy_from_slice(name.get_slice());
 				ret[8..11].copy_from_slice(ext.get_slice());
 				ret[11] = attributes;
+				FatTimestamp::write(created, &mut ret, 0x0E);
+				FatTimestamp::write(modified, &mut ret, 0x16);
 				ret[20..22].copy_from_slice(&((first_cluster >> 16) as u16).to_le_bytes());
 				ret[26..28].copy_from_slice(&(first_cluster as u16).to_le_bytes());
 				ret[28..32].copy_from_slice(&file_size.to_le_bytes());
 
 				ret
 			}
+			DirectoryEntry::LongFileName {
+				order,
+				checksum,
+				chars,
+			} => {
+				let mut ret = [0; 32];
+
+				ret[0] = order;
+				ret[11] = 0x0F;
+				ret[13] = checksum;
+				let char_offsets = (1..11)
+					.step_by(2)
+					.chain((14..26).step_by(2))
+					.chain((28..32).step_by(2));
+				for (c, offset) in chars.iter().zip(char_offsets) {
+					ret[offset..offset + 2].copy_from_slice(&c.to_le_bytes());
+				}
+
+				ret
+			}
 			_ => unimplemented!(),
 		}
 	}
@@ -1464,10 +2866,14 @@ impl Into<[u8; 32]> for DirectoryEntry {
 
 /// Initializes the FAT32 driver
 ///
+/// May be called again to mount the file system of changed partitions,
+/// in which case changes which haven't been synced are thrown away.
+///
 /// # Safety
 ///
 /// Requires partitions to have been initialized
 pub unsafe fn initialize() {
+	DRIVER = Driver::uninititalized();
 	DRIVER.initialize();
 	// for file in driver.get_entries(b"").unwrap().get_slice() {
 	// 	println!(
@@ -1479,11 +2885,41 @@ pub unsafe fn initialize() {
 	// }
 }
 
-/// Writes `data` to `path`
+/// Switches to the FAT file system on the partition with the index `partition_index`.
+///
+/// All changes to the current file system are written to disk first.
+/// If the new partition can't be mounted, the current file system stays mounted.
+pub unsafe fn mount(partition_index: u8) -> Result<(), FatError> {
+	DRIVER.sync()?;
+	let mut driver = Driver::uninititalized();
+	driver.mount(partition_index)?;
+	DRIVER = driver;
+	Ok(())
+}
+
+/// The index of the partition the file system is on
+pub unsafe fn mounted_partition() -> u8 {
+	DRIVER.partition
+}
+
+/// Writes `data` to `path`, and then makes sure all changes are written to disk
 pub unsafe fn write_file(path: Path, data: &[u8]) -> Result<(), FatError> {
+	DRIVER.write_file(path, data)?;
+	DRIVER.sync()
+}
+
+/// Writes `data` to `path`, but doesn't make sure the changes are written to disk
+///
+/// Use this when writing many files in a row, and call [sync] once done.
+pub unsafe fn write_file_deferred(path: Path, data: &[u8]) -> Result<(), FatError> {
 	DRIVER.write_file(path, data)
 }
 
+/// Writes all buffered changes to disk
+pub unsafe fn sync() -> Result<(), FatError> {
+	DRIVER.sync()
+}
+
 /// Puts the data from `path` in `buffer`
 ///
 /// Returns size of file, succeed or fail.
@@ -1491,6 +2927,24 @@ pub unsafe fn read_file(path: Path, buffer: &mut [u8]) -> Result<usize, FatError
 	DRIVER.read_file(path, buffer)
 }
 
+/// Reads the whole file at `path` into a `Vec`
+pub unsafe fn read_file_to_vec(path: Path) -> Result<Vec<u8>, FatError> {
+	DRIVER.read_file_to_vec(path)
+}
+
+/// Reads up to `buffer.len()` bytes of the file at `path`, starting at byte `offset`, into `buffer`.
+///
+/// Returns the number of bytes read, which is 0 at the end of the file,
+/// so a large file can be read piece by piece without holding all of it in memory.
+pub unsafe fn read_file_chunked(
+	path: Path,
+	offset: usize,
+	buffer: &mut [u8],
+) -> Result<usize, FatError> {
+	let file_info = DRIVER.get_file_info(path)?;
+	DRIVER.read_file_at(&file_info, offset, buffer)
+}
+
 /// Get the `FileInfo` for the file at `path`
 pub unsafe fn get_file_info(path: Path) -> FileInfo {
 	DRIVER.get_entry_info(path).unwrap()
@@ -1501,11 +2955,139 @@ pub unsafe fn list_entries(directory_path: Path) -> Result<Vec<FileInfo>, FatErr
 	DRIVER.get_entries(directory_path)
 }
 
+/// The clusters of the file or directory at `path`, in order
+pub unsafe fn file_layout(path: Path) -> Result<Vec<u32>, FatError> {
+	DRIVER.file_layout(path)
+}
+
+/// The number of free bytes, and the total number of bytes, on the file system
+pub unsafe fn free_space() -> (usize, usize) {
+	DRIVER.free_space()
+}
+
 /// `touch`
 ///
 /// Creates an empty file at `path`
 pub unsafe fn create_empty_file(path: Path) -> Result<FileInfo, FatError> {
-	DRIVER.create_empty_file(path)
+	let file_info = DRIVER.create_empty_file(path)?;
+	DRIVER.sync()?;
+	Ok(file_info)
+}
+
+/// `rm`
+///
+/// Removes the file at `path`, and frees the space it used
+pub unsafe fn delete_file(path: Path) -> Result<(), FatError> {
+	DRIVER.delete_file(path)?;
+	DRIVER.sync()
+}
+
+/// `rmdir`
+///
+/// Removes the empty directory at `path`
+pub unsafe fn remove_directory(path: Path) -> Result<(), FatError> {
+	DRIVER.remove_directory(path)?;
+	DRIVER.sync()
+}
+
+/// Renames or moves the file or directory at `from` to `to`, without moving its data
+pub unsafe fn rename(from: Path, to: Path) -> Result<(), FatError> {
+	DRIVER.rename(from, to)?;
+	DRIVER.sync()
+}
+
+/// Creates a directory at `path`, and any directories leading up to it which don't exist.
+///
+/// Fails with `FatError::AlreadyExists` if there already is something at `path`.
+pub unsafe fn create_directory(path: Path) -> Result<FileInfo, FatError> {
+	let file_info = DRIVER.create_directory(path)?;
+	DRIVER.sync()?;
+	Ok(file_info)
+}
+
+/// Makes the file or directory at `path` read-only, or writable again.
+///
+/// Read-only files can't be written to, deleted or renamed.
+pub unsafe fn set_read_only(path: Path, read_only: bool) -> Result<(), FatError> {
+	DRIVER.set_read_only(path, read_only)?;
+	DRIVER.sync()
+}
+
+/// Creates an empty file at `path`, with the attribute bits `attributes`
+///
+/// If [ATTRIBUTE_DIRECTORY] is set, a directory is created instead.
+pub unsafe fn create_file(path: Path, attributes: u8) -> Result<FileInfo, FatError> {
+	let file_info = DRIVER.create_file(path, attributes)?;
+	DRIVER.sync()?;
+	Ok(file_info)
+}
+
+/// An open file.
+///
+/// The path is only resolved once, when the file is opened.
+/// Changes to the size of the file are written to its directory entry
+/// when the handle is closed.
+#[derive(Debug)]
+pub struct FileHandle {
+	info: FileInfo,
+	/// The sector containing the file's directory entry
+	entry_sector: usize,
+	/// The byte offset of the directory entry inside `entry_sector`
+	entry_offset: usize,
+	/// If the directory entry needs to be updated on close
+	dirty: bool,
+}
+
+impl FileHandle {
+	/// The size of the file, in bytes
+	pub fn len(&self) -> usize {
+		self.info.size
+	}
+
+	/// Reads from the file, starting at byte `offset`, into `buffer`.
+	///
+	/// Returns the number of bytes read, which is 0 at the end of the file.
+	pub unsafe fn read_at(&self, offset: usize, buffer: &mut [u8]) -> Result<usize, FatError> {
+		DRIVER.read_file_at(&self.info, offset, buffer)
+	}
+
+	/// Writes `data` to the file, starting at byte `offset`.
+	///
+	/// Writing past the end of the file extends it.
+	/// `offset` may not be larger than the size of the file.
+	/// Fails with `FatError::ReadOnly` if the file is read-only.
+	///
+	/// Returns the number of bytes written.
+	pub unsafe fn write_at(&mut self, offset: usize, data: &[u8]) -> Result<usize, FatError> {
+		if self.info.is_read_only() {
+			return Err(FatError::ReadOnly);
+		}
+		let old_info = (self.info.first_cluster, self.info.size);
+		let written = DRIVER.write_file_at(&mut self.info, offset, data)?;
+		if (self.info.first_cluster, self.info.size) != old_info {
+			self.dirty = true;
+		}
+		Ok(written)
+	}
+
+	/// Closes the file, updating its directory entry and writing all changes to disk
+	pub unsafe fn close(self) -> Result<(), FatError> {
+		if self.dirty {
+			DRIVER.write_entry_at(self.entry_sector, self.entry_offset, self.info)?;
+		}
+		DRIVER.sync()
+	}
+}
+
+/// Opens the file at `path`
+pub unsafe fn open(path: Path) -> Result<FileHandle, FatError> {
+	let (info, entry_sector, entry_offset) = DRIVER.find_entry_location(path)?;
+	Ok(FileHandle {
+		info,
+		entry_sector,
+		entry_offset,
+		dirty: false,
+	})
 }
 
 /// Used to split directories from each other in paths
@@ -1535,14 +3117,7 @@ impl<T: PartialEq> SplitLast<T> for &[T] {
 	///
 	/// asserteq!(("root".as_slice(), "dir/file".as_slice()), s.get_slice().split_last_2("/"));
 	fn split_last_2(self, v: &T) -> (Self, Self) {
-		let mut last_separator_index = None;
-		for (i, b) in self.iter().enumerate() {
-			if b == v {
-				last_separator_index = Some(i);
-			}
-		}
-
-		if let Some(index) = last_separator_index {
+		if let Some(index) = self.iter().rposition(|b| b == v) {
 			let parts = self.split_at(index);
 			let dir_path = parts.0;
 			let file_name = &parts.1[1..];
@@ -1552,3 +3127,1387 @@ impl<T: PartialEq> SplitLast<T> for &[T] {
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use alloc::{format, vec, vec::Vec};
+	use core::{convert::TryFrom, sync::atomic::Ordering};
+
+	use super::{
+		super::{partitions, pata},
+		entry_in_sector, join_path, long_name_entries, normalize_path, path, short_name_alias,
+		short_name_checksum, split_path, to_long_name, to_short_name, DirectoryEntry, DirtySectors,
+		Driver, EntryCreatingIterator, EntryIterator, FatError, FatVersion, FileAllocationTable,
+		FileInfo, FsInfo, Header, LongNameBuilder, ATTRIBUTE_ARCHIVE, ATTRIBUTE_DIRECTORY,
+		ATTRIBUTE_HIDDEN, ATTRIBUTE_READ_ONLY, DIRECTORY_ENTRY_SIZE, DRIVER, FAT_SECTOR_READS,
+		SECTOR_WRITES,
+	};
+	use crate::svec::SVec;
+
+	#[test_case]
+	fn entries_in_sector() {
+		let mut sector = [0; 512];
+		entry_in_sector(&mut sector, 3)[0] = 0xE5;
+		assert_eq!(sector[3 * 32], 0xE5);
+		assert_eq!(entry_in_sector(&mut sector, 15).len(), 32);
+		assert_eq!(sector.chunks_exact(DIRECTORY_ENTRY_SIZE).count(), 16);
+	}
+
+	/// A FAT32 FAT of a single sector (128 clusters), which never touches the disk.
+	fn in_memory_fat() -> FileAllocationTable {
+		let mut fat = FileAllocationTable {
+			version: FatVersion::Fat32 {
+				root_dir_cluster: 2,
+				fsinfo_sector: 1,
+			},
+			sector_count: 1,
+			partition: 0,
+			fat_offset: 0,
+			copy_count: 1,
+			currently_loaded_sector: 0,
+			buffer: [0; 1024],
+			stored_buffer: [0; 1024],
+			dirty_sectors: DirtySectors::new(),
+			next_free_hint: 2,
+			free_count: None,
+		};
+		// Clusters 0 and 1 are reserved
+		fat.set_next_cluster(0, None).unwrap();
+		fat.set_next_cluster(1, None).unwrap();
+		fat
+	}
+
+	/// A boot sector with a FAT32 BPB if `fat32`, and a FAT16 one otherwise.
+	///
+	/// Both have 4 sectors per cluster, 32 reserved sectors and 2 FATs. `total_sectors` is put in the
+	/// 16-bit field if it fits, otherwise in the 32-bit one.
+	fn boot_sector(fat32: bool, total_sectors: u32) -> [u8; 512] {
+		let mut sector = [0; 512];
+		sector[0x03..0x0B].copy_from_slice(b"MSWIN4.1");
+		sector[0x0B..0x0D].copy_from_slice(&512u16.to_le_bytes());
+		sector[0x0D] = 4;
+		sector[0x0E..0x10].copy_from_slice(&32u16.to_le_bytes());
+		sector[0x10] = 2;
+		match u16::try_from(total_sectors) {
+			Ok(count) => sector[0x13..0x15].copy_from_slice(&count.to_le_bytes()),
+			Err(_) => sector[0x20..0x24].copy_from_slice(&total_sectors.to_le_bytes()),
+		}
+		if fat32 {
+			sector[0x24..0x28].copy_from_slice(&1000u32.to_le_bytes());
+			sector[0x2C..0x30].copy_from_slice(&2u32.to_le_bytes());
+			sector[0x30..0x32].copy_from_slice(&1u16.to_le_bytes());
+			sector[0x47..0x52].copy_from_slice(b"TEST       ");
+		} else {
+			sector[0x11..0x13].copy_from_slice(&512u16.to_le_bytes());
+			sector[0x16..0x18].copy_from_slice(&100u16.to_le_bytes());
+			sector[0x24] = 0x80;
+			sector[0x2B..0x36].copy_from_slice(b"TEST       ");
+		}
+		sector
+	}
+
+	/// A FAT16 file system of 35 sectors, which never touches the disk as long as it isn't synced.
+	///
+	/// Sector 1 is the FAT, sector 2 the root directory (16 entries), and the 32 sectors after it are
+	/// one cluster each. All of them start out as changed but unwritten sectors, so they are never read.
+	fn in_memory_fat16_driver() -> Driver {
+		let mut driver = Driver::uninititalized();
+		driver.header.sectors_per_cluster = 1;
+		driver.header.reserved_sectors = 1;
+		driver.header.fat_count = 1;
+		driver.header.sectors_per_fat = 1;
+		driver.header.root_dir_entries = 16;
+		driver.header.total_sectors = 35;
+		driver.header.fat_version = FatVersion::Fat16;
+
+		// The single FAT sector is loaded to begin with, so it is never read either
+		driver.fat.version = FatVersion::Fat16;
+		driver.fat.sector_count = 1;
+		driver.fat.fat_offset = 1;
+		driver.fat.copy_count = 1;
+		// Clusters 0 and 1 are reserved
+		driver.fat.set_next_cluster(0, None).unwrap();
+		driver.fat.set_next_cluster(1, None).unwrap();
+
+		for sector in 2..35 {
+			driver.dirty_sectors.insert(sector, &[0; 512]);
+		}
+		driver
+	}
+
+	/// A FAT32 file system of 103 sectors, which never touches the disk as long as it isn't synced.
+	///
+	/// Sector 1 is the FSInfo sector, with `free_count` as its free cluster count, and sector 2 the FAT.
+	/// The 100 sectors after it are one cluster each, the first of them the root directory.
+	/// Like [in_memory_fat16_driver], all sectors start out as changed but unwritten sectors.
+	unsafe fn in_memory_fat32_driver(free_count: u32) -> Driver {
+		let version = FatVersion::Fat32 {
+			root_dir_cluster: 2,
+			fsinfo_sector: 1,
+		};
+		let mut driver = Driver::uninititalized();
+		driver.header.sectors_per_cluster = 1;
+		driver.header.reserved_sectors = 2;
+		driver.header.fat_count = 1;
+		driver.header.sectors_per_fat = 1;
+		driver.header.total_sectors = 103;
+		driver.header.fat_version = version;
+
+		driver.fat.version = version;
+		driver.fat.sector_count = 1;
+		driver.fat.fat_offset = 2;
+		driver.fat.copy_count = 1;
+		// Clusters 0 and 1 are reserved, and the root directory is a single cluster
+		driver.fat.set_next_cluster(0, None).unwrap();
+		driver.fat.set_next_cluster(1, None).unwrap();
+		driver.fat.set_next_cluster(2, None).unwrap();
+
+		let mut fsinfo = [0; 512];
+		fsinfo[0..4].copy_from_slice(&FsInfo::LEAD_SIGNATURE.to_le_bytes());
+		fsinfo[0x1E4..0x1E8].copy_from_slice(&FsInfo::STRUCT_SIGNATURE.to_le_bytes());
+		fsinfo[0x1E8..0x1EC].copy_from_slice(&free_count.to_le_bytes());
+		fsinfo[0x1EC..0x1F0].copy_from_slice(&FsInfo::UNKNOWN.to_le_bytes());
+		driver.dirty_sectors.insert(1, &fsinfo);
+		for sector in 3..103 {
+			driver.dirty_sectors.insert(sector, &[0; 512]);
+		}
+		driver.load_fsinfo();
+		driver
+	}
+
+	#[test_case]
+	fn free_space_is_counted_when_fsinfo_count_is_invalid() {
+		unsafe {
+			// More clusters than there are, so it can't be right
+			let mut driver = in_memory_fat32_driver(1000);
+			assert_eq!(driver.fat.free_count, None);
+			// All 100 clusters but the root directory are free, and counting them reads entries past the first 64
+			assert_eq!(driver.free_space(), (99 * 512, 100 * 512));
+
+			driver.write_file(b"DATA", &[1; 70 * 512]).unwrap();
+			assert_eq!(driver.free_space(), (29 * 512, 100 * 512));
+		}
+	}
+
+	#[test_case]
+	fn unknown_fsinfo_count_is_counted_when_written() {
+		unsafe {
+			let mut driver = in_memory_fat32_driver(FsInfo::UNKNOWN);
+			assert_eq!(driver.fat.free_count, None);
+			driver.write_file(b"DATA", &[1; 10 * 512]).unwrap();
+			driver.write_fsinfo().unwrap();
+			driver.flush();
+
+			// 100 clusters, less the root directory and the file
+			let fsinfo = FsInfo::read(driver.dirty_sectors.get(1).unwrap()).unwrap();
+			assert_eq!(fsinfo.free_count, Some(89));
+		}
+	}
+
+	#[test_case]
+	fn free_clusters_are_not_counted_without_fsinfo() {
+		unsafe {
+			let mut driver = in_memory_fat16_driver();
+			driver.write_fsinfo().unwrap();
+			assert_eq!(driver.fat.free_count, None);
+		}
+	}
+
+	#[test_case]
+	fn full_root_directory_ends_at_its_last_entry() {
+		unsafe {
+			let mut driver = in_memory_fat16_driver();
+			// An entry right after the root directory, which a listing running past it would show
+			let past_the_end: [u8; 32] = DirectoryEntry::Standard {
+				file_name: SVec::try_from(&b"PAST"[..]).unwrap(),
+				attributes: 0,
+				first_cluster: 0,
+				file_size: 0,
+				created: None,
+				modified: None,
+			}
+			.into();
+			driver.load_sector(3).unwrap();
+			driver.buffer[..32].copy_from_slice(&past_the_end);
+
+			// Fills all 16 entries, leaving no empty entry at the end
+			for i in 0..16 {
+				driver
+					.create_empty_file(format!("FILE{}", i).as_bytes())
+					.unwrap();
+			}
+
+			let entries = driver.get_entries(b"").unwrap();
+			assert_eq!(entries.len(), 16);
+			assert!(!entries.iter().any(|entry| entry.has_name(b"PAST")));
+			assert!(matches!(
+				driver.create_empty_file(b"FILE16"),
+				Err(FatError::FileSystemFull)
+			));
+
+			// Nothing was written past the root directory
+			driver.load_sector(3).unwrap();
+			assert_eq!(&driver.buffer[..32], &past_the_end[..]);
+			assert_eq!(&driver.buffer[32..64], &[0; 32][..]);
+		}
+	}
+
+	#[test_case]
+	fn files_in_fixed_root_directory_are_read_back() {
+		unsafe {
+			let mut driver = in_memory_fat16_driver();
+			let first: Vec<u8> = (0..700).map(|i| i as u8).collect();
+			driver.write_file(b"FIRST.TXT", &first).unwrap();
+			driver.write_file(b"SECOND", b"second file").unwrap();
+			// Takes up two more entries for its long name
+			driver.write_file(b"the third file", b"third").unwrap();
+			driver.create_empty_file(b"EMPTY").unwrap();
+
+			let entries = driver.get_entries(b"").unwrap();
+			let names: Vec<_> = entries.iter().map(|entry| entry.display_name()).collect();
+			assert_eq!(names, ["FIRST.TXT", "SECOND", "the third file", "EMPTY"]);
+
+			assert_eq!(driver.read_file_to_vec(b"FIRST.TXT").unwrap(), first);
+			assert_eq!(driver.read_file_to_vec(b"SECOND").unwrap(), b"second file");
+			assert_eq!(
+				driver.read_file_to_vec(b"the third file").unwrap(),
+				b"third"
+			);
+			assert_eq!(driver.read_file_to_vec(b"EMPTY").unwrap(), b"");
+
+			// The entries are in the root directory sector, one after the other
+			driver.load_sector(2).unwrap();
+			assert_eq!(&driver.buffer[..11], b"FIRST   TXT");
+			assert_eq!(&driver.buffer[32..43], b"SECOND     ");
+		}
+	}
+
+	#[test_case]
+	fn total_sectors_are_read_from_either_field() {
+		let header = Header::try_new(&boot_sector(true, 1_000_000)).unwrap();
+		assert!(matches!(header.fat_version, FatVersion::Fat32 { .. }));
+		assert_eq!(header.total_sectors, 1_000_000);
+		assert_eq!(header.sectors_per_fat, 1000);
+
+		let header = Header::try_new(&boot_sector(false, 60_000)).unwrap();
+		assert!(matches!(header.fat_version, FatVersion::Fat16));
+		assert_eq!(header.total_sectors, 60_000);
+		assert_eq!(header.sectors_per_fat, 100);
+
+		let header = Header::try_new(&boot_sector(false, 100_000)).unwrap();
+		assert_eq!(header.total_sectors, 100_000);
+	}
+
+	#[test_case]
+	fn invalid_headers_are_rejected() {
+		assert!(Header::try_new(&[0; 512]).is_err());
+
+		let invalid_changes: [fn(&mut [u8; 512]); 6] = [
+			|sector| sector[0x0B..0x0D].copy_from_slice(&4096u16.to_le_bytes()),
+			|sector| sector[0x0D] = 0,
+			|sector| sector[0x0D] = 3,
+			|sector| sector[0x10] = 0,
+			|sector| sector[0x0E..0x10].copy_from_slice(&0u16.to_le_bytes()),
+			|sector| sector[0x2C..0x30].copy_from_slice(&0u32.to_le_bytes()),
+		];
+		for change in &invalid_changes {
+			let mut sector = boot_sector(true, 1_000_000);
+			change(&mut sector);
+			assert!(Header::try_new(&sector).is_err());
+		}
+
+		// A label of only spaces is empty
+		let mut sector = boot_sector(true, 1_000_000);
+		sector[0x47..0x52].copy_from_slice(b"           ");
+		assert_eq!(Header::try_new(&sector).unwrap().label.len(), 0);
+	}
+
+	#[test_case]
+	fn allocation_continues_after_last_allocated_cluster() {
+		let mut fat = in_memory_fat();
+		for expected in 2..12 {
+			let cluster = fat.find_empty_cluster().unwrap();
+			assert_eq!(cluster, expected);
+			fat.set_next_cluster(cluster, None).unwrap();
+		}
+		// A cluster freed before the hint is not reused until the search wraps around
+		fat.set_cluster_empty(3).unwrap();
+		assert_eq!(fat.find_empty_cluster(), Some(12));
+	}
+
+	#[test_case]
+	fn entries_are_at_their_byte_offsets() {
+		let mut fat = in_memory_fat();
+		fat.set_next_cluster(5, Some(9)).unwrap();
+		fat.set_next_cluster(127, None).unwrap();
+		// FAT32 entries are 4 bytes each
+		assert_eq!(fat.buffer[20..24], 9u32.to_le_bytes());
+		assert_eq!(fat.buffer[508..512], 0x0FFF_FFFFu32.to_le_bytes());
+		assert_eq!(fat.get_next_cluster(5), Some(9));
+		assert_eq!(fat.get_next_cluster(127), None);
+		assert_eq!(fat.get_next_cluster(6), Some(0));
+
+		let mut fat = in_memory_fat();
+		fat.version = FatVersion::Fat16;
+		fat.set_next_cluster(5, Some(9)).unwrap();
+		fat.set_next_cluster(255, Some(3)).unwrap();
+		// FAT16 entries are 2 bytes each
+		assert_eq!(fat.buffer[10..12], 9u16.to_le_bytes());
+		assert_eq!(fat.buffer[510..512], 3u16.to_le_bytes());
+		assert_eq!(fat.get_next_cluster(5), Some(9));
+		assert_eq!(fat.get_next_cluster(255), Some(3));
+	}
+
+	#[test_case]
+	fn allocation_wraps_around() {
+		let mut fat = in_memory_fat();
+		while let Some(cluster) = fat.find_empty_cluster() {
+			fat.set_next_cluster(cluster, None).unwrap();
+		}
+		fat.set_cluster_empty(5).unwrap();
+		assert_eq!(fat.find_empty_cluster(), Some(5));
+	}
+
+	/// Creates enough files in one directory for it to span several clusters.
+	///
+	/// This writes to the disk the tests are run from.
+	#[test_case]
+	fn directory_spanning_multiple_clusters() {
+		const FILE_COUNT: usize = 200;
+		unsafe {
+			for i in 0..FILE_COUNT {
+				DRIVER
+					.create_empty_file(format!("EFI>MANY>F{}", i).as_bytes())
+					.unwrap();
+			}
+			DRIVER.sync().unwrap();
+
+			let entries = DRIVER.get_entries(b"EFI>MANY").unwrap();
+			for i in 0..FILE_COUNT {
+				let name = format!("F{}", i);
+				assert!(entries.iter().any(|e| e.name == name.as_bytes()));
+			}
+			let last = format!("EFI>MANY>F{}", FILE_COUNT - 1);
+			assert!(DRIVER.get_entry_info(last.as_bytes()).is_ok());
+
+			for i in 0..FILE_COUNT {
+				DRIVER
+					.delete_file(format!("EFI>MANY>F{}", i).as_bytes())
+					.unwrap();
+			}
+			DRIVER.remove_directory(b"EFI>MANY").unwrap();
+			DRIVER.sync().unwrap();
+		}
+	}
+
+	#[test_case]
+	fn dirty_sectors_are_kept_sorted() {
+		let mut dirty_sectors = DirtySectors::new();
+		dirty_sectors.insert(7, &[7; 512]);
+		dirty_sectors.insert(3, &[3; 512]);
+		dirty_sectors.insert(7, &[8; 512]);
+		assert_eq!(dirty_sectors.len(), 2);
+		assert_eq!(dirty_sectors.sectors[0].0, 3);
+		assert_eq!(dirty_sectors.get(7).map(|data| data[0]), Some(8));
+		assert!(dirty_sectors.get(4).is_none());
+	}
+
+	#[test_case]
+	fn changes_are_written_at_sync() {
+		unsafe {
+			DRIVER.sync().unwrap();
+			let data: Vec<u8> = (0..4000).map(|i| i as u8).collect();
+			DRIVER.write_file(b"EFI>DEFERRED", &data).unwrap();
+			assert!(DRIVER.dirty_sectors.len() > 1);
+			// Unwritten changes are read back from memory
+			assert_eq!(DRIVER.read_file_to_vec(b"EFI>DEFERRED").unwrap(), data);
+
+			DRIVER.sync().unwrap();
+			assert_eq!(DRIVER.dirty_sectors.len(), 0);
+			assert_eq!(DRIVER.fat.dirty_sectors.len(), 0);
+
+			// Every sector of the file made it to the disk
+			let mut on_disk = Vec::new();
+			for cluster in DRIVER.file_layout(b"EFI>DEFERRED").unwrap() {
+				let mut sectors = vec![0; DRIVER.header.sectors_per_cluster * 512];
+				let sector = DRIVER.cluster_start_sector(cluster);
+				partitions::read_sectors(DRIVER.partition, sector, &mut sectors).unwrap();
+				on_disk.extend_from_slice(&sectors);
+			}
+			assert_eq!(&on_disk[..data.len()], &data[..]);
+		}
+	}
+
+	#[test_case]
+	fn free_space_is_whole_clusters() {
+		unsafe {
+			let (free, total) = DRIVER.free_space();
+			let cluster_size = DRIVER.header.sectors_per_cluster * 512;
+			assert_eq!(total, DRIVER.data_cluster_count() * cluster_size);
+			assert!(free <= total);
+			assert_eq!(free % cluster_size, 0);
+		}
+	}
+
+	#[test_case]
+	fn fsinfo_round_trips() {
+		let mut sector = [0; 512];
+		assert!(FsInfo::read(&sector).is_none());
+
+		sector[0..4].copy_from_slice(&0x4161_5252u32.to_le_bytes());
+		sector[0x1E4..0x1E8].copy_from_slice(&0x6141_7272u32.to_le_bytes());
+		sector[0x1E8..0x1F0].copy_from_slice(&[0xFF; 8]);
+		let fsinfo = FsInfo::read(&sector).unwrap();
+		assert_eq!(fsinfo.free_count, None);
+		assert_eq!(fsinfo.next_free, None);
+
+		FsInfo {
+			free_count: Some(1234),
+			next_free: Some(56),
+		}
+		.write(&mut sector);
+		let fsinfo = FsInfo::read(&sector).unwrap();
+		assert_eq!(fsinfo.free_count, Some(1234));
+		assert_eq!(fsinfo.next_free, Some(56));
+	}
+
+	#[test_case]
+	fn free_count_follows_allocations() {
+		unsafe {
+			let free_before = DRIVER.free_cluster_count();
+			DRIVER.write_file(b"EFI>COUNTED", &[1; 3000]).unwrap();
+			let cluster_count = DRIVER.file_layout(b"EFI>COUNTED").unwrap().len() as u32;
+			assert_eq!(DRIVER.free_cluster_count(), free_before - cluster_count);
+
+			DRIVER.sync().unwrap();
+			if let Some(fsinfo) = DRIVER.read_fsinfo().unwrap() {
+				assert_eq!(fsinfo.free_count, Some(free_before - cluster_count));
+			}
+
+			DRIVER.delete_file(b"EFI>COUNTED").unwrap();
+			assert_eq!(DRIVER.free_cluster_count(), free_before);
+		}
+	}
+
+	#[test_case]
+	fn rewriting_same_size_leaves_fat_alone() {
+		unsafe {
+			let bytes_per_cluster = DRIVER.header.sectors_per_cluster * 512;
+			DRIVER
+				.write_file(b"EFI>SAMESIZE", &vec![1; 2 * bytes_per_cluster])
+				.unwrap();
+			DRIVER.sync().unwrap();
+			let last_fat_cluster = (DRIVER.fat.sector_count - 1) * DRIVER.fat.clusters_per_sector();
+
+			// The FAT lookups which can't be avoided: finding the file, following its chain to write
+			// the clusters, and updating its entry, starting with the same FAT sector loaded
+			DRIVER
+				.fat
+				.load_sector_containing(last_fat_cluster as u32)
+				.unwrap();
+			let reads_before = FAT_SECTOR_READS.load(Ordering::Relaxed);
+			let info = DRIVER.get_file_info(b"EFI>SAMESIZE").unwrap();
+			DRIVER.check_chain(info.first_cluster).unwrap();
+			DRIVER.update_file_info(b"EFI>SAMESIZE", info).unwrap();
+			let lookup_reads = FAT_SECTOR_READS.load(Ordering::Relaxed) - reads_before;
+			DRIVER.sync().unwrap();
+
+			DRIVER
+				.fat
+				.load_sector_containing(last_fat_cluster as u32)
+				.unwrap();
+			let reads_before = FAT_SECTOR_READS.load(Ordering::Relaxed);
+			DRIVER
+				.write_file(b"EFI>SAMESIZE", &vec![2; 2 * bytes_per_cluster])
+				.unwrap();
+			let rewrite_reads = FAT_SECTOR_READS.load(Ordering::Relaxed) - reads_before;
+			assert_eq!(rewrite_reads, lookup_reads);
+			// Nothing in the FAT was changed
+			DRIVER.fat.flush();
+			assert_eq!(DRIVER.fat.dirty_sectors.len(), 0);
+
+			assert_eq!(
+				DRIVER.read_file_to_vec(b"EFI>SAMESIZE").unwrap(),
+				vec![2; 2 * bytes_per_cluster]
+			);
+			DRIVER.delete_file(b"EFI>SAMESIZE").unwrap();
+			DRIVER.sync().unwrap();
+		}
+	}
+
+	/// Writes 32 small files into the new directory `dir`, like the `test` shell command,
+	/// with `write` and then [super::sync].
+	///
+	/// Returns the number of FAT sectors read and the number of sectors written, and removes everything again.
+	unsafe fn count_io_of_writing_files(
+		dir: &[u8],
+		write: unsafe fn(&[u8], &[u8]) -> Result<(), FatError>,
+	) -> (usize, usize) {
+		DRIVER.create_directory(dir).unwrap();
+		DRIVER.sync().unwrap();
+		let paths: Vec<Vec<u8>> = (0..32)
+			.map(|i| [dir, format!(">{}", i).as_bytes()].concat())
+			.collect();
+
+		let reads_before = FAT_SECTOR_READS.load(Ordering::Relaxed);
+		let writes_before = SECTOR_WRITES.load(Ordering::Relaxed);
+		for (i, path) in paths.iter().enumerate() {
+			write(path, format!("File number {}\n", i).as_bytes()).unwrap();
+		}
+		super::sync().unwrap();
+		let reads = FAT_SECTOR_READS.load(Ordering::Relaxed) - reads_before;
+		let writes = SECTOR_WRITES.load(Ordering::Relaxed) - writes_before;
+
+		for path in &paths {
+			DRIVER.delete_file(path).unwrap();
+		}
+		DRIVER.remove_directory(dir).unwrap();
+		DRIVER.sync().unwrap();
+		(reads, writes)
+	}
+
+	/// This writes to the disk the tests are run from.
+	#[test_case]
+	fn deferred_writes_need_less_io() {
+		unsafe {
+			let (synced_reads, synced_writes) =
+				count_io_of_writing_files(b"EFI>SYNCED", super::write_file);
+			let (deferred_reads, deferred_writes) =
+				count_io_of_writing_files(b"EFI>DEFERRED", super::write_file_deferred);
+			// The FAT stays loaded when it is synced, so it isn't read any more often
+			assert!(deferred_reads <= synced_reads);
+			// Every synced write also writes the FSInfo sector, the directory sector and the FAT sector
+			// of every FAT copy, which deferred writes only write once
+			assert!(deferred_writes * 2 < synced_writes);
+		}
+	}
+
+	#[test_case]
+	fn file_handles_write_and_extend_files() {
+		unsafe {
+			let bytes_per_cluster = DRIVER.header.sectors_per_cluster * 512;
+			super::write_file(b"EFI>HANDLED", b"0123456789").unwrap();
+
+			let mut file = super::open(b"EFI>HANDLED").unwrap();
+			assert_eq!(file.write_at(2, b"ab").unwrap(), 2);
+			// Extends the file past the end of its first cluster
+			let tail = vec![b'x'; bytes_per_cluster];
+			assert_eq!(file.write_at(10, &tail).unwrap(), tail.len());
+			assert_eq!(file.len(), 10 + bytes_per_cluster);
+			file.close().unwrap();
+
+			let info = DRIVER.get_file_info(b"EFI>HANDLED").unwrap();
+			assert_eq!(info.size, 10 + bytes_per_cluster);
+			assert_eq!(DRIVER.file_layout(b"EFI>HANDLED").unwrap().len(), 2);
+			let mut expected = b"01ab456789".to_vec();
+			expected.extend_from_slice(&tail);
+			assert_eq!(super::read_file_to_vec(b"EFI>HANDLED").unwrap(), expected);
+
+			// Writing inside the file doesn't change its size
+			let mut file = super::open(b"EFI>HANDLED").unwrap();
+			file.write_at(0, b"AB").unwrap();
+			file.close().unwrap();
+			assert_eq!(
+				DRIVER.get_file_info(b"EFI>HANDLED").unwrap().size,
+				expected.len()
+			);
+			assert_eq!(
+				&super::read_file_to_vec(b"EFI>HANDLED").unwrap()[..4],
+				b"ABab"
+			);
+			super::delete_file(b"EFI>HANDLED").unwrap();
+		}
+	}
+
+	#[test_case]
+	fn empty_files_are_read_as_empty() {
+		unsafe {
+			DRIVER.create_empty_file(b"EFI>EMPTY").unwrap();
+			assert_eq!(DRIVER.read_file_to_vec(b"EFI>EMPTY").unwrap(), b"");
+
+			// A file which is emptied has its clusters freed
+			DRIVER.write_file(b"EFI>EMPTY", &[7; 1000]).unwrap();
+			DRIVER.write_file(b"EFI>EMPTY", b"").unwrap();
+			assert_eq!(
+				DRIVER.get_entry_info(b"EFI>EMPTY").unwrap().first_cluster,
+				0
+			);
+			assert_eq!(DRIVER.read_file_to_vec(b"EFI>EMPTY").unwrap(), b"");
+			DRIVER.delete_file(b"EFI>EMPTY").unwrap();
+		}
+	}
+
+	#[test_case]
+	fn failed_sync_keeps_unwritten_sectors() {
+		unsafe {
+			let drive = partitions::list_partitions()[DRIVER.partition as usize].drive();
+			DRIVER.write_file(b"EFI>PROTECT", b"written later").unwrap();
+
+			pata::set_read_only(drive, true);
+			let result = DRIVER.sync();
+			pata::set_read_only(drive, false);
+			assert!(matches!(
+				result,
+				Err(FatError::Disk(pata::PataError::ReadOnly))
+			));
+			assert!(DRIVER.dirty_sectors.len() > 0);
+
+			DRIVER.sync().unwrap();
+			assert_eq!(DRIVER.dirty_sectors.len(), 0);
+			assert_eq!(
+				super::read_file_to_vec(b"EFI>PROTECT").unwrap(),
+				b"written later"
+			);
+			super::delete_file(b"EFI>PROTECT").unwrap();
+		}
+	}
+
+	#[test_case]
+	fn partitions_can_be_mounted_again() {
+		unsafe {
+			DRIVER.write_file(b"EFI>MOUNTED", b"still here").unwrap();
+			let partition = super::mounted_partition();
+
+			assert!(matches!(super::mount(200), Err(FatError::NoFileSystem)));
+			// A failed mount keeps the current file system
+			assert_eq!(super::mounted_partition(), partition);
+			assert_eq!(
+				DRIVER.read_file_to_vec(b"EFI>MOUNTED").unwrap(),
+				b"still here"
+			);
+
+			super::mount(partition).unwrap();
+			assert_eq!(DRIVER.fat.partition, partition);
+			assert_eq!(
+				DRIVER.read_file_to_vec(b"EFI>MOUNTED").unwrap(),
+				b"still here"
+			);
+			DRIVER.delete_file(b"EFI>MOUNTED").unwrap();
+		}
+	}
+
+	#[test_case]
+	fn all_fat_copies_are_written() {
+		unsafe {
+			DRIVER.write_file(b"EFI>MIRRORED", &[2; 3000]).unwrap();
+			DRIVER.sync().unwrap();
+
+			let fat_sectors = DRIVER.header.sectors_per_fat;
+			let clusters_per_sector = DRIVER.fat.clusters_per_sector();
+			for cluster in DRIVER.file_layout(b"EFI>MIRRORED").unwrap() {
+				let sector = cluster as usize / clusters_per_sector;
+				let mut first_copy = [0; 512];
+				partitions::read_sectors(
+					DRIVER.partition,
+					DRIVER.fat.fat_offset + sector,
+					&mut first_copy,
+				)
+				.unwrap();
+				for copy in 1..DRIVER.header.fat_count {
+					let mut other_copy = [0; 512];
+					let copy_sector = DRIVER.fat.fat_offset + copy * fat_sectors + sector;
+					partitions::read_sectors(DRIVER.partition, copy_sector, &mut other_copy).unwrap();
+					assert_eq!(first_copy[..], other_copy[..]);
+				}
+			}
+		}
+	}
+
+	#[test_case]
+	fn renamed_files_keep_their_data() {
+		unsafe {
+			DRIVER.write_file(b"EFI>OLD.TXT", b"renamed").unwrap();
+			let clusters = DRIVER.file_layout(b"EFI>OLD.TXT").unwrap();
+
+			DRIVER.rename(b"EFI>OLD.TXT", b"EFI>NEW.TXT").unwrap();
+			assert!(matches!(
+				DRIVER.get_entry_info(b"EFI>OLD.TXT"),
+				Err(FatError::PathNotFound)
+			));
+			assert_eq!(DRIVER.read_file_to_vec(b"EFI>NEW.TXT").unwrap(), b"renamed");
+
+			DRIVER
+				.create_file(b"EFI>DEST", ATTRIBUTE_DIRECTORY)
+				.unwrap();
+			DRIVER
+				.rename(b"EFI>NEW.TXT", b"EFI>DEST>moved with a long name.txt")
+				.unwrap();
+			assert!(DRIVER.get_entry_info(b"EFI>NEW.TXT").is_err());
+			let path = b"EFI>DEST>moved with a long name.txt";
+			assert_eq!(DRIVER.read_file_to_vec(path).unwrap(), b"renamed");
+			assert_eq!(DRIVER.file_layout(path).unwrap(), clusters);
+		}
+	}
+
+	#[test_case]
+	fn moved_directories_point_to_their_new_parent() {
+		unsafe {
+			DRIVER.create_empty_file(b"EFI>MOVEDIR>INNER").unwrap();
+			DRIVER
+				.create_file(b"EFI>NEWPARENT", ATTRIBUTE_DIRECTORY)
+				.unwrap();
+			DRIVER
+				.rename(b"EFI>MOVEDIR", b"EFI>NEWPARENT>MOVEDIR")
+				.unwrap();
+			assert!(DRIVER
+				.get_entry_info(b"EFI>NEWPARENT>MOVEDIR>INNER")
+				.is_ok());
+
+			let new_parent = DRIVER.get_entry_info(b"EFI>NEWPARENT").unwrap();
+			let entries = DRIVER.get_entries(b"EFI>NEWPARENT>MOVEDIR").unwrap();
+			let dot_dot = entries.iter().find(|e| e.name == b".."[..]).unwrap();
+			assert_eq!(dot_dot.first_cluster, new_parent.first_cluster);
+		}
+	}
+
+	#[test_case]
+	fn invalid_renames_fail() {
+		unsafe {
+			DRIVER.create_empty_file(b"EFI>RENAME1").unwrap();
+			DRIVER.create_empty_file(b"EFI>RENAME2").unwrap();
+			assert!(matches!(
+				DRIVER.rename(b"EFI>RENAME1", b"EFI>RENAME2"),
+				Err(FatError::AlreadyExists)
+			));
+			assert!(matches!(
+				DRIVER.rename(b"EFI>RENAME1", b"EFI>NOWHERE>RENAME1"),
+				Err(FatError::PathNotFound)
+			));
+			assert!(matches!(
+				DRIVER.rename(b"EFI", b"EFI>INSIDE"),
+				Err(FatError::MovedIntoItself)
+			));
+			// Changing only the case is fine
+			DRIVER.rename(b"EFI>RENAME1", b"EFI>rename1").unwrap();
+			assert!(DRIVER.get_entry_info(b"EFI>RENAME1").is_ok());
+		}
+	}
+
+	#[test_case]
+	fn creating_existing_file_fails() {
+		unsafe {
+			DRIVER.create_empty_file(b"EFI>TWICE").unwrap();
+			assert!(matches!(
+				DRIVER.create_empty_file(b"EFI>TWICE"),
+				Err(FatError::AlreadyExists)
+			));
+		}
+	}
+
+	#[test_case]
+	fn cyclic_cluster_chain_is_corrupt() {
+		unsafe {
+			DRIVER.write_file(b"EFI>CYCLE", b"cycle").unwrap();
+			let cluster = DRIVER.get_entry_info(b"EFI>CYCLE").unwrap().first_cluster;
+			// Make the only cluster of the file point to itself
+			DRIVER.fat.set_next_cluster(cluster, Some(cluster)).unwrap();
+			let mut buffer = [0; 512];
+			let result = DRIVER.read_file(b"EFI>CYCLE", &mut buffer);
+			DRIVER.fat.set_next_cluster(cluster, None).unwrap();
+			assert!(matches!(result, Err(FatError::CorruptChain)));
+		}
+	}
+
+	#[test_case]
+	fn timestamps_are_kept_when_entries_change() {
+		// 2021-03-14 15:09:26, and 1999-12-31 23:59:58
+		let mut raw_entry: [u8; 32] = DirectoryEntry::Standard {
+			file_name: to_short_name(b"STAMPED").unwrap(),
+			attributes: 0,
+			first_cluster: 0,
+			file_size: 0,
+			created: None,
+			modified: None,
+		}
+		.into();
+		raw_entry[0x0E..0x12].copy_from_slice(&[0x2D, 0x79, 0x6E, 0x52]);
+		raw_entry[0x16..0x1A].copy_from_slice(&[0x7D, 0xBF, 0x9F, 0x27]);
+
+		let mut entry = DirectoryEntry::try_from(&raw_entry[..]).unwrap();
+		let (created, modified) = match entry {
+			DirectoryEntry::Standard {
+				created, modified, ..
+			} => (created.unwrap(), modified.unwrap()),
+			_ => panic!("Not a standard entry"),
+		};
+		assert_eq!((created.year, created.month, created.day), (2021, 3, 14));
+		assert_eq!((created.hour, created.minute, created.second), (15, 9, 26));
+		assert_eq!(format!("{}", modified).as_str(), "1999-12-31 23:59:58");
+
+		// Updating the entry, like `update_file_info` does, keeps the timestamps in the info
+		entry.update(FileInfo {
+			name: to_short_name(b"STAMPED").unwrap(),
+			size: 10,
+			is_directory: false,
+			attributes: 0,
+			long_name: None,
+			created: Some(created),
+			modified: Some(modified),
+			first_cluster: 0,
+		});
+		let updated: [u8; 32] = entry.into();
+		assert_eq!(updated[0x0E..0x1A], raw_entry[0x0E..0x1A]);
+		assert_eq!(updated[28], 10);
+	}
+
+	#[test_case]
+	fn chunked_reads_span_clusters() {
+		unsafe {
+			let bytes_per_cluster = DRIVER.header.sectors_per_cluster * 512;
+			let data: Vec<u8> = (0..bytes_per_cluster * 2 + 300)
+				.map(|i| (i % 253) as u8)
+				.collect();
+			DRIVER.write_file(b"EFI>CHUNKED", &data).unwrap();
+
+			// Odd-sized chunks, starting mid-cluster, so some land across cluster boundaries
+			let start = bytes_per_cluster / 2 + 7;
+			let mut read_back = Vec::new();
+			let mut buffer = [0; 333];
+			loop {
+				let offset = start + read_back.len();
+				let read = super::read_file_chunked(b"EFI>CHUNKED", offset, &mut buffer).unwrap();
+				if read == 0 {
+					break;
+				}
+				read_back.extend_from_slice(&buffer[..read]);
+			}
+			assert_eq!(&read_back[..], &data[start..]);
+			assert_eq!(
+				super::read_file_chunked(b"EFI>CHUNKED", data.len() + 10, &mut buffer).unwrap(),
+				0
+			);
+			DRIVER.delete_file(b"EFI>CHUNKED").unwrap();
+		}
+	}
+
+	#[test_case]
+	fn cyclic_directory_chain_is_rejected() {
+		unsafe {
+			DRIVER.create_directory(b"EFI>LOOPDIR").unwrap();
+			let cluster = DRIVER.get_entry_info(b"EFI>LOOPDIR").unwrap().first_cluster;
+			DRIVER.fat.set_next_cluster(cluster, Some(cluster)).unwrap();
+			let iterating = EntryIterator::new(&mut DRIVER, b"EFI>LOOPDIR").map(|_| ());
+			let creating = EntryCreatingIterator::new(&mut DRIVER, b"EFI>LOOPDIR").map(|_| ());
+			let listing = DRIVER.get_entries(b"EFI>LOOPDIR").map(|_| ());
+			DRIVER.fat.set_next_cluster(cluster, None).unwrap();
+			assert!(matches!(iterating, Err(FatError::CorruptChain)));
+			assert!(matches!(creating, Err(FatError::CorruptChain)));
+			assert!(matches!(listing, Err(FatError::CorruptChain)));
+			DRIVER.remove_directory(b"EFI>LOOPDIR").unwrap();
+		}
+	}
+
+	#[test_case]
+	fn files_round_trip_at_size_boundaries() {
+		unsafe {
+			let bytes_per_cluster = DRIVER.header.sectors_per_cluster * 512;
+			// Growing and then shrinking back to 0 exercises both extending and truncating the chain
+			let sizes = [
+				0,
+				1,
+				511,
+				512,
+				513,
+				bytes_per_cluster,
+				bytes_per_cluster + 1,
+				512,
+				0,
+			];
+			for &size in &sizes {
+				let data: Vec<u8> = (0..size).map(|i| (i % 251) as u8).collect();
+				DRIVER.write_file(b"EFI>SIZES", &data).unwrap();
+				let info = DRIVER.get_entry_info(b"EFI>SIZES").unwrap();
+				assert_eq!(info.size, size);
+				assert_eq!(info.first_cluster == 0, size == 0);
+				assert_eq!(DRIVER.read_file_to_vec(b"EFI>SIZES").unwrap(), data);
+			}
+		}
+	}
+
+	#[test_case]
+	fn short_names_are_upper_case() {
+		assert_eq!(
+			to_short_name(b"readme.txt").unwrap().get_slice(),
+			b"README.TXT"
+		);
+		assert_eq!(to_short_name(b"Mixed_1").unwrap().get_slice(), b"MIXED_1");
+		assert_eq!(to_short_name(b"A~1.{}").unwrap().get_slice(), b"A~1.{}");
+	}
+
+	#[test_case]
+	fn invalid_short_names_are_rejected() {
+		for &name in &[
+			&b""[..],
+			b".txt",
+			b"toolongname",
+			b"file.text",
+			b"with space",
+			b"star*",
+			b"a+b.c",
+			b"q?.txt",
+			b"a.b.c",
+			"åäö".as_bytes(),
+		] {
+			assert!(matches!(to_short_name(name), Err(FatError::InvalidName)));
+		}
+	}
+
+	#[test_case]
+	fn lower_case_names_are_found() {
+		unsafe {
+			DRIVER.create_empty_file(b"EFI>lower.txt").unwrap();
+			assert_eq!(
+				DRIVER
+					.get_entry_info(b"EFI>LOWER.TXT")
+					.unwrap()
+					.name
+					.get_slice(),
+				b"LOWER.TXT"
+			);
+			assert!(DRIVER.get_entry_info(b"EFI>Lower.Txt").is_ok());
+			assert!(matches!(
+				DRIVER.create_empty_file(b"EFI>bad|name"),
+				Err(FatError::InvalidName)
+			));
+		}
+	}
+
+	#[test_case]
+	fn read_only_files_cant_be_written() {
+		unsafe {
+			let info = DRIVER
+				.create_file(b"EFI>LOCKED", ATTRIBUTE_READ_ONLY | ATTRIBUTE_ARCHIVE)
+				.unwrap();
+			assert!(info.is_read_only());
+			let info = DRIVER.get_entry_info(b"EFI>LOCKED").unwrap();
+			assert_eq!(info.attributes, ATTRIBUTE_READ_ONLY | ATTRIBUTE_ARCHIVE);
+			assert!(matches!(
+				DRIVER.write_file(b"EFI>LOCKED", b"data"),
+				Err(FatError::ReadOnly)
+			));
+			assert_eq!(DRIVER.get_entry_info(b"EFI>LOCKED").unwrap().size, 0);
+
+			DRIVER.set_read_only(b"EFI>LOCKED", false).unwrap();
+			DRIVER.delete_file(b"EFI>LOCKED").unwrap();
+			DRIVER.sync().unwrap();
+		}
+	}
+
+	#[test_case]
+	fn read_only_attribute_can_be_toggled() {
+		unsafe {
+			DRIVER.write_file(b"EFI>TOGGLED", b"data").unwrap();
+			DRIVER.set_read_only(b"EFI>TOGGLED", true).unwrap();
+			assert!(DRIVER
+				.get_entry_info(b"EFI>TOGGLED")
+				.unwrap()
+				.is_read_only());
+			assert!(matches!(
+				DRIVER.delete_file(b"EFI>TOGGLED"),
+				Err(FatError::ReadOnly)
+			));
+			assert!(matches!(
+				DRIVER.rename(b"EFI>TOGGLED", b"EFI>MOVED"),
+				Err(FatError::ReadOnly)
+			));
+			DRIVER.set_read_only(b"EFI>TOGGLED", false).unwrap();
+			let info = DRIVER.get_entry_info(b"EFI>TOGGLED").unwrap();
+			assert!(!info.is_read_only());
+			assert_eq!(info.size, 4);
+			DRIVER.delete_file(b"EFI>TOGGLED").unwrap();
+
+			// The directory bit is kept when the read-only bit changes
+			DRIVER.set_read_only(b"EFI", true).unwrap();
+			let info = DRIVER.get_entry_info(b"EFI").unwrap();
+			DRIVER.set_read_only(b"EFI", false).unwrap();
+			assert!(info.is_directory && info.is_read_only());
+			assert!(DRIVER.get_entry_info(b"EFI").unwrap().is_directory);
+			assert!(matches!(
+				DRIVER.set_read_only(b"", true),
+				Err(FatError::IsRootDirectory)
+			));
+		}
+	}
+
+	#[test_case]
+	fn created_directories_have_dot_entries() {
+		unsafe {
+			let parent = DRIVER.get_entry_info(b"EFI").unwrap();
+			let info = DRIVER.create_directory(b"EFI>MADE>INNER").unwrap();
+			assert!(info.is_directory);
+			assert!(matches!(
+				DRIVER.create_directory(b"EFI>MADE>INNER"),
+				Err(FatError::AlreadyExists)
+			));
+			// Only the last directory has to be new
+			DRIVER.create_directory(b"EFI>MADE>OTHER").unwrap();
+
+			let made = DRIVER.get_entry_info(b"EFI>MADE").unwrap();
+			let entries = DRIVER.get_entries(b"EFI>MADE").unwrap();
+			assert_eq!(entries[0].name, b"."[..]);
+			assert_eq!(entries[0].first_cluster, made.first_cluster);
+			assert_eq!(entries[1].name, b".."[..]);
+			assert_eq!(entries[1].first_cluster, parent.first_cluster);
+
+			// The names are padded with spaces, and not split into name and extension
+			DRIVER
+				.load_sector(DRIVER.cluster_start_sector(made.first_cluster))
+				.unwrap();
+			assert_eq!(&DRIVER.buffer[..11], b".          ");
+			assert_eq!(&DRIVER.buffer[32..43], b"..         ");
+		}
+	}
+
+	#[test_case]
+	fn dot_dot_leads_back_to_the_parent() {
+		unsafe {
+			DRIVER.create_directory(b"DOTTOP>SUB").unwrap();
+			let top = DRIVER.get_entry_info(b"DOTTOP").unwrap();
+			let sub = DRIVER.get_entry_info(b"DOTTOP>SUB").unwrap();
+
+			let top_entries = DRIVER.get_entries(b"DOTTOP").unwrap();
+			let sub_entries = DRIVER.get_entries(b"DOTTOP>SUB").unwrap();
+			assert_eq!(top_entries[0].first_cluster, top.first_cluster);
+			// The root directory is stored as 0
+			assert_eq!(top_entries[1].first_cluster, 0);
+			assert_eq!(sub_entries[0].first_cluster, sub.first_cluster);
+			assert_eq!(sub_entries[1].first_cluster, top.first_cluster);
+
+			// Going into the subdirectory and back out through `..` gives the parent again
+			let names = |entries: Vec<FileInfo>| {
+				entries
+					.into_iter()
+					.map(|entry| entry.name)
+					.collect::<Vec<_>>()
+			};
+			let through_dot_dot = DRIVER.get_entries(b"DOTTOP>SUB>..").unwrap();
+			assert_eq!(names(through_dot_dot), names(top_entries));
+			let root_through_dot_dot = DRIVER.get_entries(b"DOTTOP>SUB>..>..").unwrap();
+			assert_eq!(
+				names(root_through_dot_dot),
+				names(DRIVER.get_entries(b"").unwrap())
+			);
+
+			DRIVER.remove_directory(b"DOTTOP>SUB").unwrap();
+			DRIVER.remove_directory(b"DOTTOP").unwrap();
+		}
+	}
+
+	#[test_case]
+	fn directories_can_be_created_with_attributes() {
+		unsafe {
+			DRIVER
+				.create_file(b"EFI>HIDDEN", ATTRIBUTE_DIRECTORY | ATTRIBUTE_HIDDEN)
+				.unwrap();
+			let info = DRIVER.get_entry_info(b"EFI>HIDDEN").unwrap();
+			assert!(info.is_directory);
+			assert_eq!(info.attributes, ATTRIBUTE_DIRECTORY | ATTRIBUTE_HIDDEN);
+		}
+	}
+
+	#[test_case]
+	fn deleted_files_are_gone() {
+		unsafe {
+			DRIVER.write_file(b"EFI>DELETED", &[0xAB; 2000]).unwrap();
+			let clusters = DRIVER.file_layout(b"EFI>DELETED").unwrap();
+			DRIVER.delete_file(b"EFI>DELETED").unwrap();
+			assert!(matches!(
+				DRIVER.get_entry_info(b"EFI>DELETED"),
+				Err(FatError::PathNotFound)
+			));
+			for cluster in clusters {
+				assert_eq!(DRIVER.fat.get_next_cluster(cluster), Some(0));
+			}
+			assert!(matches!(
+				DRIVER.delete_file(b"EFI>DELETED"),
+				Err(FatError::PathNotFound)
+			));
+			assert!(matches!(
+				DRIVER.delete_file(b"EFI"),
+				Err(FatError::IsDirectory)
+			));
+		}
+	}
+
+	#[test_case]
+	fn only_empty_directories_are_removed() {
+		unsafe {
+			DRIVER.create_empty_file(b"EFI>FULL>FILE").unwrap();
+			assert!(matches!(
+				DRIVER.remove_directory(b"EFI>FULL"),
+				Err(FatError::DirectoryNotEmpty)
+			));
+			assert!(matches!(
+				DRIVER.remove_directory(b"EFI>FULL>FILE"),
+				Err(FatError::IsntDirectory)
+			));
+			DRIVER.delete_file(b"EFI>FULL>FILE").unwrap();
+			DRIVER.remove_directory(b"EFI>FULL").unwrap();
+			assert!(matches!(
+				DRIVER.get_entry_info(b"EFI>FULL"),
+				Err(FatError::PathNotFound)
+			));
+			assert!(matches!(
+				DRIVER.remove_directory(b""),
+				Err(FatError::IsRootDirectory)
+			));
+		}
+	}
+
+	/// Builds the raw LFN entry holding `part` (at most 13 characters) of a long name
+	fn lfn_entry(order: u8, checksum: u8, part: &str) -> [u8; 32] {
+		let mut chars = [0xFFFF; 13];
+		let mut len = 0;
+		for (c, u) in chars.iter_mut().zip(part.encode_utf16()) {
+			*c = u;
+			len += 1;
+		}
+		if len < 13 {
+			chars[len] = 0;
+		}
+		let mut entry = [0; 32];
+		entry[0] = order;
+		entry[11] = 0x0F;
+		entry[13] = checksum;
+		let offsets = (1..11)
+			.step_by(2)
+			.chain((14..26).step_by(2))
+			.chain((28..32).step_by(2));
+		for (c, offset) in chars.iter().zip(offsets) {
+			entry[offset..offset + 2].copy_from_slice(&c.to_le_bytes());
+		}
+		entry
+	}
+
+	/// Feeds the raw entry `entry` to `builder`, like the directory listing does
+	fn add_lfn_entry(builder: &mut LongNameBuilder, entry: &[u8; 32]) {
+		match DirectoryEntry::try_from(&entry[..]) {
+			Ok(DirectoryEntry::LongFileName {
+				order,
+				checksum,
+				chars,
+			}) => builder.add_entry(order, checksum, &chars),
+			_ => panic!("Not an LFN entry"),
+		}
+	}
+
+	#[test_case]
+	fn long_names_are_put_together() {
+		let short_name = b"LONGFI~1TXT";
+		let checksum = short_name_checksum(short_name);
+		let mut builder = LongNameBuilder::new();
+		// Stored last part first
+		add_lfn_entry(&mut builder, &lfn_entry(0x42, checksum, "e name.txt"));
+		add_lfn_entry(&mut builder, &lfn_entry(0x01, checksum, "Long file nam"));
+		assert_eq!(
+			builder.finish(short_name).as_deref(),
+			Some("Long file name.txt")
+		);
+
+		// Exactly filling the entry, with non-ASCII characters
+		add_lfn_entry(&mut builder, &lfn_entry(0x41, checksum, "Räksmörgås.md"));
+		assert_eq!(builder.finish(short_name).as_deref(), Some("Räksmörgås.md"));
+
+		// The builder is reset by finishing
+		assert_eq!(builder.finish(short_name), None);
+	}
+
+	#[test_case]
+	fn broken_long_names_are_ignored() {
+		let short_name = b"LONGFI~1TXT";
+		let checksum = short_name_checksum(short_name);
+		let mut builder = LongNameBuilder::new();
+
+		// Wrong checksum
+		add_lfn_entry(
+			&mut builder,
+			&lfn_entry(0x41, checksum.wrapping_add(1), "name"),
+		);
+		assert_eq!(builder.finish(short_name), None);
+
+		// Missing the first part
+		add_lfn_entry(&mut builder, &lfn_entry(0x42, checksum, "e name.txt"));
+		assert_eq!(builder.finish(short_name), None);
+
+		// Out of order
+		add_lfn_entry(&mut builder, &lfn_entry(0x01, checksum, "Long file nam"));
+		add_lfn_entry(&mut builder, &lfn_entry(0x42, checksum, "e name.txt"));
+		assert_eq!(builder.finish(short_name), None);
+
+		// An orphaned part followed by a complete name
+		add_lfn_entry(&mut builder, &lfn_entry(0x43, checksum, "orphan"));
+		add_lfn_entry(&mut builder, &lfn_entry(0x41, checksum, "short"));
+		assert_eq!(builder.finish(short_name).as_deref(), Some("short"));
+	}
+
+	#[test_case]
+	fn short_name_aliases() {
+		assert_eq!(
+			short_name_alias("a_very_long_filename.txt", 1).get_slice(),
+			b"A_VERY~1.TXT"
+		);
+		assert_eq!(
+			short_name_alias("a_very_long_filename.txt", 12).get_slice(),
+			b"A_VER~12.TXT"
+		);
+		assert_eq!(
+			short_name_alias("long name.text", 2).get_slice(),
+			b"LONGNA~2.TEX"
+		);
+		assert_eq!(short_name_alias(".bashrc", 1).get_slice(), b"BASHRC~1");
+		assert_eq!(
+			short_name_alias("räksmörgås.md", 1).get_slice(),
+			b"R_KSM_~1.MD"
+		);
+		assert_eq!(short_name_alias("a+b.c.d", 3).get_slice(), b"A_BC~3.D");
+	}
+
+	#[test_case]
+	fn invalid_long_names_are_rejected() {
+		assert_eq!(to_long_name(b"long name.text").unwrap(), "long name.text");
+		let too_long = [b'a'; 256];
+		for &name in &[
+			&b""[..],
+			b"..",
+			b"  ",
+			b"a|b",
+			b"tab\t",
+			b"quote\"",
+			&too_long,
+		] {
+			assert!(matches!(to_long_name(name), Err(FatError::InvalidName)));
+		}
+	}
+
+	#[test_case]
+	fn long_name_entries_are_read_back() {
+		let short_name = b"A_VERY~1TXT";
+		let entries = long_name_entries("a_very_long_filename.txt", short_name);
+		assert_eq!(entries.len(), 2);
+		assert_eq!(entries[0][0], 0x42);
+		assert_eq!(entries[1][0], 0x01);
+		let mut builder = LongNameBuilder::new();
+		for entry in &entries {
+			add_lfn_entry(&mut builder, entry);
+		}
+		assert_eq!(
+			builder.finish(short_name).as_deref(),
+			Some("a_very_long_filename.txt")
+		);
+	}
+
+	#[test_case]
+	fn long_names_round_trip() {
+		unsafe {
+			let info = DRIVER
+				.create_empty_file(b"EFI>a_very_long_filename.txt")
+				.unwrap();
+			assert_eq!(info.name.get_slice(), b"A_VERY~1.TXT");
+			DRIVER
+				.write_file(b"EFI>a_very_long_filename.txt", b"long")
+				.unwrap();
+			assert_eq!(
+				DRIVER.read_file_to_vec(b"EFI>A_VERY~1.TXT").unwrap(),
+				b"long"
+			);
+			assert!(DRIVER
+				.get_entries(b"EFI")
+				.unwrap()
+				.iter()
+				.any(|entry| entry.display_name() == "a_very_long_filename.txt"));
+			assert!(matches!(
+				DRIVER.create_empty_file(b"EFI>A_Very_Long_Filename.txt"),
+				Err(FatError::AlreadyExists)
+			));
+
+			// The alias of a similar name mustn't collide
+			let info = DRIVER
+				.create_empty_file(b"EFI>a_very_long_filename.txt2")
+				.unwrap();
+			assert_eq!(info.name.get_slice(), b"A_VERY~2.TXT");
+
+			DRIVER
+				.create_empty_file(b"EFI>Long directory name>file")
+				.unwrap();
+			let info = DRIVER.get_entry_info(b"EFI>long directory name").unwrap();
+			assert!(info.is_directory);
+			assert_eq!(info.long_name.as_deref(), Some("Long directory name"));
+
+			DRIVER.delete_file(b"EFI>a_very_long_filename.txt").unwrap();
+			assert!(DRIVER.get_entry_info(b"EFI>A_VERY~1.TXT").is_err());
+			assert!(DRIVER
+				.get_entry_info(b"EFI>a_very_long_filename.txt2")
+				.is_ok());
+		}
+	}
+
+	#[test_case]
+	fn updating_removed_entry_fails() {
+		unsafe {
+			let mut driver = in_memory_fat16_driver();
+			driver.create_empty_file(b"GONE").unwrap();
+			let (info, sector, offset) = driver.find_entry_location(b"GONE").unwrap();
+			// Mark the entry as unused, as if it was deleted after being looked up
+			driver.load_sector(sector).unwrap();
+			driver.buffer[offset] = 0xE5;
+			assert!(matches!(
+				driver.update_file_info(b"GONE", info),
+				Err(FatError::PathNotFound)
+			));
+		}
+	}
+
+	#[test_case]
+	fn root_dir_entries_are_packed_into_sectors() {
+		let mut driver = Driver::uninititalized();
+		driver.header.reserved_sectors = 1;
+		driver.header.fat_count = 2;
+		driver.header.sectors_per_fat = 9;
+		driver.header.root_dir_entries = 224;
+		// The root directory starts right after the FATs
+		assert_eq!(driver.root_dir_entry_location(0), (19, 0));
+		assert_eq!(driver.root_dir_entry_location(15), (19, 480));
+		assert_eq!(driver.root_dir_entry_location(16), (20, 0));
+		assert_eq!(driver.root_dir_entry_location(223), (32, 480));
+	}
+
+	#[test_case]
+	fn dot_and_dot_dot_are_resolved() {
+		assert_eq!(normalize_path(b"EFI>.>BOOT"), b"EFI>BOOT");
+		assert_eq!(normalize_path(b"EFI>..>other"), b"other");
+		assert_eq!(normalize_path(b"EFI>BOOT>..>..>file"), b"file");
+		assert_eq!(normalize_path(b"..>..>EFI"), b"EFI");
+		assert_eq!(normalize_path(b".."), b"");
+		assert_eq!(normalize_path(b""), b"");
+	}
+
+	#[test_case]
+	fn both_separators_resolve_the_same() {
+		assert_eq!(normalize_path(b"EFI/BOOT"), normalize_path(b"EFI>BOOT"));
+		assert_eq!(normalize_path(b"EFI/BOOT>..>x"), b"EFI>x");
+		assert_eq!(split_path(b"EFI/BOOT/file"), split_path(b"EFI>BOOT>file"));
+		assert_eq!(
+			split_path(b"EFI>BOOT/file"),
+			(&b"EFI>BOOT"[..], &b"file"[..])
+		);
+	}
+
+	#[test_case]
+	fn joining_paths() {
+		assert_eq!(join_path(b"", b"file"), b"file");
+		assert_eq!(join_path(b"EFI", b"file"), b"EFI>file");
+		assert_eq!(join_path(b"EFI>", b"file"), b"EFI>file");
+		assert_eq!(join_path(b"EFI/BOOT", b"file"), b"EFI/BOOT>file");
+	}
+
+	#[test_case]
+	fn paths_from_strings() {
+		assert_eq!(path("EFI/BOOT/BOOTX64.EFI"), b"EFI>BOOT>BOOTX64.EFI");
+		assert_eq!(path("EFI>BOOT"), b"EFI>BOOT");
+		assert_eq!(path(""), b"");
+		// Separators at the ends, or next to each other, don't make empty parts
+		assert_eq!(path("/EFI"), b"EFI");
+		assert_eq!(path("EFI//BOOT/"), b"EFI>BOOT");
+		assert_eq!(path(">"), b"");
+	}
+}
@@ -1,8 +1,28 @@
 pub mod fat32;
-mod partitions;
+pub mod partitions;
 mod pata;
 
+/// Initializes the drives, the partitions on them, and the file system.
 pub unsafe fn initialize() {
+	initialize_with_dry_run(false);
+}
+
+/// Finds the drives, the partitions and the file system again, for when the disk has been swapped.
+///
+/// Changes to the old file system are written first, so this should be done before the disk is swapped
+/// if anything was written with [fat32::write_file_deferred]. Fails without rescanning if they can't be written.
+pub unsafe fn rescan() -> Result<(), fat32::FatError> {
+	fat32::sync()?;
+	pata::rescan();
+	partitions::initialize();
+	fat32::initialize();
+	Ok(())
+}
+
+/// Like [initialize], but if `dry_run` is set nothing is ever written to the disk.
+/// The writes are printed instead, see [pata::set_dry_run].
+pub unsafe fn initialize_with_dry_run(dry_run: bool) {
+	pata::set_dry_run(dry_run);
 	pata::initialize();
 	partitions::initialize();
 	fat32::initialize();
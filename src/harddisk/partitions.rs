@@ -1,3 +1,5 @@
+use core::fmt::{Debug, Display};
+
 use crate::{harddisk::pata, svec::SVec};
 
 // Layouts from OSDev wiki: https://wiki.osdev.org/GPT
@@ -35,12 +37,145 @@ use crate::{harddisk::pata, svec::SVec};
 // 0x38 (72) - Partition name
 
 const NUM_PARTITIONS: usize = 16;
+/// The drive the partition table is read from
+const DRIVE: u8 = 0;
+
+/// A GUID, stored as the 16 bytes on disk.
+///
+/// The first three fields are stored little-endian, and the last two big-endian,
+/// so the bytes are not in the order they are written in the standard form.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Guid([u8; 16]);
+
+impl Guid {
+	/// Creates a GUID from its fields, in the order they are written in the standard form.
+	///
+	/// `C12A7328-F81F-11D2-BA4B-00A0C93EC93B` is
+	/// `Guid::new(0xC12A7328, 0xF81F, 0x11D2, [0xBA, 0x4B, 0x00, 0xA0, 0xC9, 0x3E, 0xC9, 0x3B])`
+	pub const fn new(a: u32, b: u16, c: u16, d: [u8; 8]) -> Self {
+		let a = a.to_le_bytes();
+		let b = b.to_le_bytes();
+		let c = c.to_le_bytes();
+		Self([
+			a[0], a[1], a[2], a[3], b[0], b[1], c[0], c[1], d[0], d[1], d[2], d[3], d[4], d[5], d[6],
+			d[7],
+		])
+	}
+
+	/// The GUID as the 16 bytes on disk
+	pub fn as_bytes(&self) -> &[u8; 16] {
+		&self.0
+	}
+}
+
+impl From<[u8; 16]> for Guid {
+	/// Uses the bytes as they are stored on disk
+	fn from(bytes: [u8; 16]) -> Self {
+		Self(bytes)
+	}
+}
+
+/// Prints the GUID in the standard `xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx` form
+impl Display for Guid {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		let g = &self.0;
+		write!(
+			f,
+			"{:08X}-{:04X}-{:04X}-",
+			u32::from_le_bytes([g[0], g[1], g[2], g[3]]),
+			u16::from_le_bytes([g[4], g[5]]),
+			u16::from_le_bytes([g[6], g[7]])
+		)?;
+		for b in &g[8..10] {
+			write!(f, "{:02X}", b)?;
+		}
+		write!(f, "-")?;
+		for b in &g[10..16] {
+			write!(f, "{:02X}", b)?;
+		}
+		Ok(())
+	}
+}
+
+impl Debug for Guid {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		write!(f, "{}", self)
+	}
+}
+
+const EFI_SYSTEM_PARTITION: Guid = Guid::new(
+	0xC12A7328,
+	0xF81F,
+	0x11D2,
+	[0xBA, 0x4B, 0x00, 0xA0, 0xC9, 0x3E, 0xC9, 0x3B],
+);
+const MICROSOFT_BASIC_DATA: Guid = Guid::new(
+	0xEBD0A0A2,
+	0xB9E5,
+	0x4433,
+	[0x87, 0xC0, 0x68, 0xB6, 0xB7, 0x26, 0x99, 0xC7],
+);
+
+/// Names of common partition types
+const PARTITION_TYPE_NAMES: [(Guid, &str); 6] = [
+	(EFI_SYSTEM_PARTITION, "EFI System Partition"),
+	(MICROSOFT_BASIC_DATA, "Microsoft Basic Data"),
+	(
+		Guid::new(
+			0xE3C9E316,
+			0x0B5C,
+			0x4DB8,
+			[0x81, 0x7D, 0xF9, 0x2D, 0xF0, 0x02, 0x15, 0xAE],
+		),
+		"Microsoft Reserved",
+	),
+	(
+		Guid::new(
+			0x21686148,
+			0x6449,
+			0x6E6F,
+			[0x74, 0x4E, 0x65, 0x65, 0x64, 0x45, 0x46, 0x49],
+		),
+		"BIOS Boot",
+	),
+	(
+		Guid::new(
+			0x0FC63DAF,
+			0x8483,
+			0x4772,
+			[0x8E, 0x79, 0x3D, 0x69, 0xD8, 0x47, 0x7D, 0xE4],
+		),
+		"Linux Filesystem",
+	),
+	(
+		Guid::new(
+			0x0657FD6D,
+			0xA4AB,
+			0x43C4,
+			[0x84, 0xE5, 0x09, 0x33, 0xC8, 0x4B, 0x4F, 0x4F],
+		),
+		"Linux Swap",
+	),
+];
+
+/// The name of the partition type `type_guid`, or "Unknown"
+pub fn partition_type_name(type_guid: Guid) -> &'static str {
+	for (guid, name) in &PARTITION_TYPE_NAMES {
+		if *guid == type_guid {
+			return name;
+		}
+	}
+	"Unknown"
+}
 
 static mut PARTITIONS: SVec<Partition, NUM_PARTITIONS> = SVec::new();
 
 pub struct Partition {
 	index: u8,
-	partition_guid: [u8; 16],
+	/// The drive the partition is on
+	drive: u8,
+	partition_type_guid: Guid,
+	partition_guid: Guid,
 	start_sector: usize,
 	sector_count: usize,
 	name: SVec<char, 36>,
@@ -51,12 +186,33 @@ impl Partition {
 		self.index
 	}
 
-	pub fn partition_guid(&self) -> &[u8] {
-		&self.partition_guid
+	/// The drive the partition is on
+	pub fn drive(&self) -> u8 {
+		self.drive
+	}
+
+	/// The GUID identifying what the partition is used for
+	pub fn partition_type_guid(&self) -> Guid {
+		self.partition_type_guid
+	}
+
+	/// The name of the partition type, or "Unknown"
+	pub fn type_name(&self) -> &'static str {
+		partition_type_name(self.partition_type_guid)
+	}
+
+	/// Is this a type of partition which usually contains a FAT file system?
+	pub fn is_fat_type(&self) -> bool {
+		self.partition_type_guid == EFI_SYSTEM_PARTITION
+			|| self.partition_type_guid == MICROSOFT_BASIC_DATA
+	}
+
+	pub fn partition_guid(&self) -> Guid {
+		self.partition_guid
 	}
 
 	pub fn start_sector(&self) -> usize {
-		self.sector_count
+		self.start_sector
 	}
 
 	pub fn sector_count(&self) -> usize {
@@ -68,7 +224,7 @@ impl Partition {
 	}
 }
 
-/// Initializes and populates the partition information array for disk 0
+/// Initializes and populates the partition information array for disk [DRIVE]
 ///
 /// # Safety
 ///
@@ -76,10 +232,13 @@ impl Partition {
 ///
 /// The module 'pata' must be initialized before this function is called
 pub unsafe fn initialize() {
+	// This may be run again after the disk has changed
+	PARTITIONS = SVec::new();
+
 	let mut buf = [0 as u8; 512];
 
 	// Read GPT Header from disk (sector 1)
-	pata::read_sectors(0, 1, &mut buf);
+	pata::read_sectors(DRIVE, 1, &mut buf).expect("Failed to read GPT header");
 	// Make sure it's a GPT header
 	if !buf.starts_with(&[0x45, 0x46, 0x49, 0x20, 0x50, 0x41, 0x52, 0x54]) {
 		panic!("No GUID Partition Table found on disk");
@@ -108,7 +267,7 @@ pub unsafe fn initialize() {
 	let mut partition_index: u8 = 0;
 	for s in start_sector..last_sector {
 		// Read disk sector
-		pata::read_sectors(0, s, &mut buf);
+		pata::read_sectors(DRIVE, s, &mut buf).expect("Failed to read partition entries");
 		// Read individual partition entry
 		for p in 0..num_entries_per_slice {
 			let base_offset: usize = (partition_entry_size * p) as usize;
@@ -169,9 +328,12 @@ pub unsafe fn initialize() {
 			// Make partition entry
 			let entry = Partition {
 				index: partition_index,
-				partition_guid,
+				drive: DRIVE,
+				partition_type_guid: partition_type_guid.into(),
+				partition_guid: partition_guid.into(),
 				start_sector,
-				sector_count: (last_sector - start_sector),
+				// The last sector is inclusive
+				sector_count: (last_sector - start_sector + 1),
 				name,
 			};
 
@@ -198,38 +360,183 @@ pub unsafe fn list_partitions() -> &'static [Partition] {
 	return PARTITIONS.get_slice();
 }
 
+/// Translates a transfer of `buffer_len` bytes starting at `start_sector` in `partition`
+/// to the drive and the first sector on it.
+///
+/// Fails if any of the sectors are outside of the partition.
+fn sector_on_disk(
+	partition: &Partition,
+	start_sector: usize,
+	buffer_len: usize,
+) -> Result<(u8, usize), ()> {
+	let end_sector = start_sector + (buffer_len + 511) / 512;
+	if end_sector > partition.sector_count {
+		return Err(());
+	}
+	Ok((partition.drive, partition.start_sector + start_sector))
+}
+
 /// Reads sectors from specified partition
 /// start_sector starts at 0
-pub unsafe fn read_sectors(partition: u8, start_sector: usize, buffer: &mut [u8]) {
+///
+/// Fails if the drive doesn't respond.
+pub unsafe fn read_sectors(
+	partition: u8,
+	start_sector: usize,
+	buffer: &mut [u8],
+) -> Result<(), pata::PataError> {
 	if buffer.len() % 512 != 0 {
 		panic!("Buffer must be a multiple of 512 bytes");
 	}
 
-	let sector_count = PARTITIONS[partition as usize].sector_count;
-	if start_sector >= sector_count {
-		panic!("sector out of range");
-	}
-
-	let sector = PARTITIONS[partition as usize].start_sector + start_sector;
-	pata::read_sectors(partition, sector, buffer);
+	let (drive, sector) =
+		match sector_on_disk(&PARTITIONS[partition as usize], start_sector, buffer.len()) {
+			Ok(location) => location,
+			Err(()) => panic!(
+				"Read of {} sectors at sector {} is outside of partition {}",
+				buffer.len() / 512,
+				start_sector,
+				partition
+			),
+		};
+	pata::read_sectors(drive, sector, buffer)
 }
 
 // Writes sectors to specified partition
 /// start_sector starts at 0
-pub unsafe fn write_sectors(partition: u8, start_sector: usize, buffer: &[u8]) {
+///
+/// Fails if the drive is read-only, or doesn't respond.
+pub unsafe fn write_sectors(
+	partition: u8,
+	start_sector: usize,
+	buffer: &[u8],
+) -> Result<(), pata::PataError> {
 	if buffer.len() % 512 != 0 {
 		panic!("Buffer must be a multiple of 512 bytes");
 	}
 
-	if buffer.len() % 512 != 0 {
-		panic!("Buffer must be a multiple of 512 bytes");
+	let (drive, sector) =
+		match sector_on_disk(&PARTITIONS[partition as usize], start_sector, buffer.len()) {
+			Ok(location) => location,
+			Err(()) => panic!(
+				"Write of {} sectors at sector {} is outside of partition {}",
+				buffer.len() / 512,
+				start_sector,
+				partition
+			),
+		};
+	pata::write_sectors(drive, sector, buffer)
+}
+
+#[cfg(test)]
+mod tests {
+	use alloc::format;
+
+	use super::{
+		partition_type_name, read_sectors, sector_on_disk, write_sectors, Guid, Partition, PARTITIONS,
+	};
+	use crate::{
+		harddisk::{fat32, pata},
+		svec::SVec,
+	};
+
+	#[test_case]
+	fn guid_is_displayed_mixed_endian() {
+		// The EFI System Partition type GUID, as stored on disk
+		let bytes = [
+			0x28, 0x73, 0x2A, 0xC1, 0x1F, 0xF8, 0xD2, 0x11, 0xBA, 0x4B, 0x00, 0xA0, 0xC9, 0x3E, 0xC9,
+			0x3B,
+		];
+		let guid = Guid::from(bytes);
+		assert_eq!(format!("{}", guid), "C12A7328-F81F-11D2-BA4B-00A0C93EC93B");
+		assert_eq!(
+			guid,
+			Guid::new(
+				0xC12A7328,
+				0xF81F,
+				0x11D2,
+				[0xBA, 0x4B, 0x00, 0xA0, 0xC9, 0x3E, 0xC9, 0x3B]
+			)
+		);
+	}
+
+	#[test_case]
+	fn known_partition_types_have_names() {
+		let esp = Guid::new(
+			0xC12A7328,
+			0xF81F,
+			0x11D2,
+			[0xBA, 0x4B, 0x00, 0xA0, 0xC9, 0x3E, 0xC9, 0x3B],
+		);
+		assert_eq!(partition_type_name(esp), "EFI System Partition");
+		assert_eq!(partition_type_name(Guid::from([0x12; 16])), "Unknown");
+	}
+
+	#[test_case]
+	fn transfers_must_fit_in_partition() {
+		let partition = Partition {
+			index: 3,
+			drive: 0,
+			partition_type_guid: Guid::from([0; 16]),
+			partition_guid: Guid::from([0; 16]),
+			start_sector: 100,
+			sector_count: 10,
+			name: SVec::new(),
+		};
+		// The index of the partition isn't the drive
+		assert_eq!(sector_on_disk(&partition, 0, 512), Ok((0, 100)));
+		assert_eq!(sector_on_disk(&partition, 8, 1024), Ok((0, 108)));
+		// Straddles the end of the partition
+		assert_eq!(sector_on_disk(&partition, 9, 1024), Err(()));
+		assert_eq!(sector_on_disk(&partition, 10, 512), Err(()));
 	}
 
-	let sector_count = PARTITIONS[partition as usize].sector_count;
-	if start_sector >= sector_count {
-		panic!("sector out of range");
+	#[test_case]
+	fn dry_run_writes_nothing() {
+		unsafe {
+			let last_sector = PARTITIONS[0].sector_count - 1;
+			let mut before = [0; 512];
+			read_sectors(0, last_sector, &mut before).unwrap();
+
+			pata::set_dry_run(true);
+			let result = write_sectors(0, last_sector, &[!before[0]; 512]);
+			pata::set_dry_run(false);
+			assert!(result.is_ok());
+
+			let mut after = [0; 512];
+			read_sectors(0, last_sector, &mut after).unwrap();
+			assert_eq!(before[..], after[..]);
+		}
 	}
 
-	let sector = PARTITIONS[partition as usize].start_sector + start_sector;
-	pata::write_sectors(partition, sector, buffer);
+	#[test_case]
+	fn partitions_after_the_first_can_be_mounted() {
+		unsafe {
+			let mounted = fat32::mounted_partition();
+			fat32::write_file(b"EFI>SECOND", b"on both").unwrap();
+
+			// The same sectors again, under an index which isn't 0
+			let first = &PARTITIONS[mounted as usize];
+			let copy = Partition {
+				index: PARTITIONS.len() as u8,
+				drive: first.drive,
+				partition_type_guid: first.partition_type_guid,
+				partition_guid: first.partition_guid,
+				start_sector: first.start_sector,
+				sector_count: first.sector_count,
+				name: first.name.clone(),
+			};
+			let index = copy.index;
+			PARTITIONS.push(copy);
+
+			fat32::mount(index).unwrap();
+			assert_eq!(fat32::mounted_partition(), index);
+			let result = fat32::read_file_to_vec(b"EFI>SECOND");
+
+			fat32::mount(mounted).unwrap();
+			PARTITIONS.pop();
+			assert_eq!(result.unwrap(), b"on both");
+			fat32::delete_file(b"EFI>SECOND").unwrap();
+		}
+	}
 }
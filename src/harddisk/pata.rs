@@ -1,4 +1,7 @@
-use core::{sync::atomic::AtomicBool, usize};
+use core::{
+	sync::atomic::{AtomicBool, Ordering},
+	usize,
+};
 
 use x86_64::instructions::port::{Port, PortReadOnly, PortWriteOnly};
 
@@ -45,6 +48,9 @@ static mut MAX_ITER: usize = 2000;
 /// Contains the information on the drives/disks
 static mut DRIVES: SVec<DriveInfo, 2> = SVec::new();
 
+/// If writes are only logged, instead of sent to the drive. See [set_dry_run].
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
 /// Intitialize the primary drive bus, and all drives on it.
 /// # Safety
 /// All port I/O can threaten safety.
@@ -61,6 +67,16 @@ pub unsafe fn initialize() {
 	}
 }
 
+/// Identifies the drives again, for when they have been swapped (like disk images in QEMU).
+///
+/// Overrides made with [set_read_only] are lost.
+/// # Safety
+/// Same as [initialize], and nothing may be reading from or writing to the drives.
+pub unsafe fn rescan() {
+	DRIVES = SVec::new();
+	initialize();
+}
+
 /// Initializes a particular drive, and returns it's info.
 unsafe fn initialize_drive(drive: u8) -> DriveInfo {
 	let mut disk = DriveInfo {
@@ -133,9 +149,26 @@ unsafe fn initialize_drive(drive: u8) -> DriveInfo {
 	}
 	disk.status = DriveStatus::Connected;
 	wait_till_idle();
+	if is_write_protected(&disk) {
+		disk.status = DriveStatus::ReadOnly;
+	}
 	disk
 }
 
+/// Checks whether the currently selected drive reports its media as write-protected.
+///
+/// This is only possible if the drive supports removable media status notification (IDENTIFY word 127),
+/// in which case GET MEDIA STATUS sets WP (bit 6) in the error register.
+unsafe fn is_write_protected(disk: &DriveInfo) -> bool {
+	if disk.identify_result[127] & 0b11 != 0b01 {
+		return false;
+	}
+	COMMAND_REG.write(0xDA); // GET MEDIA STATUS
+	wait_till_idle();
+	let status = STATUS_REG.read();
+	status & 1 == 1 && ERROR_REG.read() & 0x40 == 0x40
+}
+
 /// Info about the particular drive
 #[derive(Clone)]
 pub struct DriveInfo {
@@ -159,13 +192,23 @@ pub enum DriveStatus {
 	Connected,
 	/// Drive is missing
 	Disconnected,
-	/// Drive is read-only (unused)
+	/// Drive is connected, but can only be read from.
+	/// Either the media is write-protected, or it was set with `set_read_only`.
 	ReadOnly,
 	/// Drive status unknown:
 	/// Not a drive, non ATA/LBA drive, or error getting info off it.
 	Unknown,
 }
 
+/// Errors returned to the caller, as opposed to the panics for misuse of the driver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PataError {
+	/// Attempted write to a read-only drive.
+	ReadOnly,
+	/// The drive didn't respond, even after being reset.
+	Timeout,
+}
+
 enum Errors {
 	/// Address mark not found.
 	AMNF = 0b0000_0001,
@@ -190,98 +233,159 @@ pub unsafe fn get_drives() -> SVec<DriveInfo, 2> /* or bigger*/ {
 	DRIVES.clone()
 }
 
+/// Overrides whether `drive` should be treated as read-only.
+///
+/// Has no effect on drives which are not connected.
+pub unsafe fn set_read_only(drive: u8, read_only: bool) {
+	if drive > 1 {
+		panic!("No support for more than 2 drives")
+	}
+	let status = &mut DRIVES[drive as usize].status;
+	match status {
+		DriveStatus::Connected | DriveStatus::ReadOnly => {
+			*status = if read_only {
+				DriveStatus::ReadOnly
+			} else {
+				DriveStatus::Connected
+			}
+		}
+		_ => {}
+	}
+}
+
+/// Makes [write_sectors] print the sector and length of each write instead of writing anything,
+/// so writing code can be tried out without changing the disk. Read-only drives can be "written" to as well.
+pub fn set_dry_run(dry_run: bool) {
+	DRY_RUN.store(dry_run, Ordering::Relaxed);
+}
+
 /// Fills up the provided slice with data from disk, starting with `start_sector`
 /// This means the slice needs to have a size that's a multiple of 512.
+///
+/// If the drive stops responding, the whole read is retried (see [with_retries]),
+/// and `PataError::Timeout` is returned if it never does.
 /// # Safety:
 /// The contents/existance of a disk to read from is not checked.
-pub unsafe fn read_sectors(drive: u8, start_sector: usize, buffer: &mut [u8]) {
+pub unsafe fn read_sectors(
+	drive: u8,
+	start_sector: usize,
+	buffer: &mut [u8],
+) -> Result<(), PataError> {
 	if buffer.len() % 512 != 0 {
 		panic!("Buffer must be a multiple of 512 bytes");
 	}
 	if drive > 1 {
 		panic!("No support for more than 2 drives")
 	}
-	if DRIVES[drive as usize].status != DriveStatus::Connected {
-		panic!("Attempt to read non-connected drive")
+	match DRIVES[drive as usize].status {
+		DriveStatus::Connected | DriveStatus::ReadOnly => {}
+		_ => panic!("Attempt to read non-connected drive"),
 	}
 	while BUSY.load(core::sync::atomic::Ordering::Acquire) {}
 	BUSY.store(true, core::sync::atomic::Ordering::Release);
 	let lba = buffer.len() / 512;
 	let lba48 = DRIVES[drive as usize].lba48;
 
-	select_drive(drive, lba);
-	send_lba_and_sector_count(start_sector, lba, lba48);
-	wait_till_idle();
-	if lba48 {
-		COMMAND_REG.write(0x24); // READ SECTORS EXT
-	} else {
-		COMMAND_REG.write(0x20); // READ SECTORS
-	}
-
-	for i in 0..buffer.len() / 512 {
-		poll();
-		for j in 0..256 {
-			let val = DATA_REG.read().to_le_bytes();
-			buffer[i * 512 + j * 2] = val[0];
-			buffer[i * 512 + j * 2 + 1] = val[1];
+	let result = with_retries(|attempt| {
+		select_drive(drive, lba);
+		send_lba_and_sector_count(start_sector, lba, lba48);
+		wait_till_idle();
+		if lba48 {
+			COMMAND_REG.write(0x24); // READ SECTORS EXT
+		} else {
+			COMMAND_REG.write(0x20); // READ SECTORS
 		}
-		for _ in 0..MAX_ITER / 400 {
-			// 5 times minimum
-			STATUS_REG.read();
+
+		for i in 0..buffer.len() / 512 {
+			poll(attempt)?;
+			for j in 0..256 {
+				let val = DATA_REG.read().to_le_bytes();
+				buffer[i * 512 + j * 2] = val[0];
+				buffer[i * 512 + j * 2 + 1] = val[1];
+			}
+			for _ in 0..MAX_ITER / 400 {
+				// 5 times minimum
+				STATUS_REG.read();
+			}
 		}
-	}
-	wait_till_idle();
+		wait_till_idle();
+		Ok(())
+	});
 	BUSY.store(false, core::sync::atomic::Ordering::Release);
+	result
 }
 
 /// Writes the provided slice to the disk, starting at `start_sector`
 /// Means slice has to be a multiple of 512 bytes.
+///
+/// Returns `PataError::ReadOnly`, without writing anything, if the drive is read-only.
+/// If the drive stops responding, the whole write is retried (see [with_retries]),
+/// and `PataError::Timeout` is returned if it never does.
 /// # Safety:
 /// This driver has no idea what is stored where, padding with 0 might for instance overwrite the filesystem.
-pub unsafe fn write_sectors(drive: u8, start_sector: usize, buffer: &[u8]) {
+pub unsafe fn write_sectors(
+	drive: u8,
+	start_sector: usize,
+	buffer: &[u8],
+) -> Result<(), PataError> {
 	if buffer.len() % 512 != 0 {
 		panic!("Buffer must be a multiple of 512 bytes");
 	}
 	if drive > 1 {
 		panic!("No support for more than 2 drives")
 	}
-	if DRIVES[drive as usize].status != DriveStatus::Connected {
-		panic!("Attempted write to non-connected disk")
+	match DRIVES[drive as usize].status {
+		DriveStatus::Connected | DriveStatus::ReadOnly if DRY_RUN.load(Ordering::Relaxed) => {
+			println!(
+				"Dry run: write of {} sectors at LBA {} on drive {}",
+				buffer.len() / 512,
+				start_sector,
+				drive
+			);
+			return Ok(());
+		}
+		DriveStatus::Connected => {}
+		DriveStatus::ReadOnly => return Err(PataError::ReadOnly),
+		_ => panic!("Attempted write to non-connected disk"),
 	}
 	while BUSY.load(core::sync::atomic::Ordering::Acquire) {}
 	BUSY.store(true, core::sync::atomic::Ordering::Release);
 	let lba = buffer.len() / 512;
 	let lba48 = DRIVES[drive as usize].lba48;
 
-	select_drive(drive, lba);
-	send_lba_and_sector_count(start_sector, lba, lba48);
-	wait_till_idle();
-	if lba48 {
-		COMMAND_REG.write(0x34); // WRITE SECTORS EXT
-	} else {
-		COMMAND_REG.write(0x30) // WRITE SECTORS
-	}
+	let result = with_retries(|attempt| {
+		select_drive(drive, lba);
+		send_lba_and_sector_count(start_sector, lba, lba48);
+		wait_till_idle();
+		if lba48 {
+			COMMAND_REG.write(0x34); // WRITE SECTORS EXT
+		} else {
+			COMMAND_REG.write(0x30) // WRITE SECTORS
+		}
 
-	for i in 0..buffer.len() / 512 {
-		poll();
-		for j in 0..256 {
-			let val = u16::from_le_bytes([buffer[i * 512 + j * 2], buffer[i * 512 + j * 2 + 1]]);
-			DATA_REG.write(val);
-			for _ in 0..MAX_ITER / 200 {
-				// 10 times minimum
-				asm!("jmp no_op", "no_op:", options(nostack, nomem));
+		for i in 0..buffer.len() / 512 {
+			poll(attempt)?;
+			for j in 0..256 {
+				let val = u16::from_le_bytes([buffer[i * 512 + j * 2], buffer[i * 512 + j * 2 + 1]]);
+				DATA_REG.write(val);
+				for _ in 0..MAX_ITER / 200 {
+					// 10 times minimum
+					asm!("jmp no_op", "no_op:", options(nostack, nomem));
+				}
+			}
+			for _ in 0..MAX_ITER / 400 {
+				// 5 times minimum
+				STATUS_REG.read();
 			}
 		}
-		for _ in 0..MAX_ITER / 400 {
-			// 5 times minimum
-			STATUS_REG.read();
-		}
-	}
-	wait_till_idle();
-	//Flush cache
-	COMMAND_REG.write(0xE7);
-	wait_till_idle();
+		wait_till_idle();
+		//Flush cache
+		COMMAND_REG.write(0xE7);
+		wait_till_idle();
+		Ok(())
+	});
 	BUSY.store(false, core::sync::atomic::Ordering::Release);
+	result
 }
 
 /// Polls the drive until it's idle.
@@ -294,10 +398,37 @@ unsafe fn wait_till_idle() {
 	}
 }
 
+/// Runs `transfer` from the start, which includes selecting the drive and sending the command,
+/// until it doesn't time out.
+///
+/// `transfer` is given the number of the attempt, starting at 0, to pass on to [poll].
+/// The drives are reset before every retry, which forgets the selected drive and any command in progress.
+/// After `MAX_RETRIES` retries, `PataError::Timeout` is returned.
+unsafe fn with_retries(
+	mut transfer: impl FnMut(u32) -> Result<(), PataError>,
+) -> Result<(), PataError> {
+	const MAX_RETRIES: u32 = 4;
+
+	let mut attempt = 0;
+	loop {
+		match transfer(attempt) {
+			Err(PataError::Timeout) if attempt < MAX_RETRIES => {
+				software_reset();
+				attempt += 1;
+			}
+			result => return result,
+		}
+	}
+}
+
 /// Polls the status of selected drive, breaking when it's finished.
-unsafe fn poll() {
+///
+/// Returns `PataError::Timeout` if the drive doesn't respond. Later attempts of a transfer
+/// (see [with_retries]) wait twice as long as the one before.
+unsafe fn poll(attempt: u32) -> Result<(), PataError> {
 	//Time to poll (we be singletasking)
 	let mut iter = 1;
+	let iterations_before_timeout = MAX_ITER << attempt;
 	loop {
 		let status = STATUS_REG.read();
 		let bsy = status & 0x80 == 0x80;
@@ -308,16 +439,14 @@ unsafe fn poll() {
 			//TODO: error handling
 			panic!("Harddisk error")
 		} else if !bsy && drq {
+			// Only a retry can take longer than `MAX_ITER`, which means the drive is slower than measured so far
 			if MAX_ITER < iter {
 				MAX_ITER = iter;
 			}
-			break;
-		}
-		if iter % MAX_ITER == 0 {
-			software_reset();
+			return Ok(());
 		}
-		if iter % (MAX_ITER * 100) == 0 {
-			panic!("Hardrive polling time-out")
+		if iter >= iterations_before_timeout {
+			return Err(PataError::Timeout);
 		}
 		iter += 1;
 	}
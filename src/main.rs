@@ -11,37 +11,49 @@
 #![feature(const_generics)]
 #![feature(const_evaluatable_checked)]
 #![feature(default_alloc_error_handler)]
+#![feature(custom_test_frameworks)]
+#![test_runner(crate::test::runner)]
+#![reexport_test_harness_main = "test_main"]
 
 extern crate alloc;
 extern crate rlibc;
 
 #[macro_use]
 mod printer;
+#[macro_use]
+mod serial;
 mod allocator;
 mod gdt;
 mod harddisk;
 mod idt;
 mod pic;
+mod pit;
 mod ps2;
 mod ps2_keyboard;
+mod shell;
+mod sstring;
 mod svec;
+#[cfg(test)]
+mod test;
+mod viewer;
 
-use alloc::format;
 use core::{
 	panic::PanicInfo,
 	sync::atomic::{AtomicBool, Ordering},
 };
 
 use bootloader::BootInfo;
-use harddisk::fat32::FatError;
 
-use crate::{ps2_keyboard::KeyCode, svec::SVec};
+use crate::{ps2_keyboard::KeyCode, sstring::SString, svec::SVec};
 
 #[no_mangle]
 pub extern "C" fn _start(boot_info: &'static BootInfo) -> ! {
 	// No function call may precede this one, or else undefined behaviour may be invoked.
 	initialize(boot_info);
 
+	#[cfg(test)]
+	test_main();
+
 	println!("Hello, World!");
 
 	let mut path_buffer: SVec<u8, 128> = SVec::new();
@@ -49,63 +61,8 @@ pub extern "C" fn _start(boot_info: &'static BootInfo) -> ! {
 	loop {
 		let event = ps2_keyboard::get_key_event();
 		if event.keycode == KeyCode::Enter {
-			use harddisk::fat32::SplitLast;
 			println!();
-			match path_buffer.get_slice().split_last_2(&b' ') {
-				(b"read", path) => match unsafe { harddisk::fat32::list_entries(path) } {
-					Ok(e) => {
-						for e in e {
-							println!(
-								"{:12}  {:3}  {}",
-								e.name.to_str(),
-								if e.is_directory { "DIR" } else { "   " },
-								e.size
-							);
-						}
-					}
-					Err(FatError::IsntDirectory) => {
-						let mut buffer = [0; 1024 * 2];
-						match unsafe { harddisk::fat32::read_file(path, &mut buffer) } {
-							Ok(v) => {
-								println!("{}", core::str::from_utf8(&buffer[0..v]).unwrap());
-							}
-							Err(e) => println!("Error: {:#?}", e),
-						}
-					}
-					Err(e) => {
-						println!("Error: {:#?}", e)
-					}
-				},
-				(b"create", path) => match unsafe { harddisk::fat32::create_empty_file(path) } {
-					Ok(info) => println!("{:#?}", info),
-					Err(e) => println!("Error: {:#?}", e),
-				},
-				(b"write", path) => {
-					let data_to_write = include_bytes!("../file_to_write.txt");
-					match unsafe { harddisk::fat32::write_file(path, data_to_write) } {
-						Ok(_) => {}
-						Err(e) => println!("Error: {:#?}", e),
-					}
-				}
-				(b"test", _) => {
-					for i in 0..32 {
-						println!("Creating file {}", i);
-						match unsafe {
-							harddisk::fat32::write_file(
-								format!("EFI>{}", i).as_bytes(),
-								format!("File number {}\n", i).as_bytes(),
-							)
-						} {
-							Ok(_) => {}
-							Err(e) => println!("Error: {:#?}", e),
-						}
-					}
-				}
-				(other, _) => println!(
-					"Unrecognized command '{}'",
-					core::str::from_utf8(other).unwrap()
-				),
-			}
+			unsafe { shell::run(path_buffer.get_slice()) };
 			while path_buffer.len() > 0 {
 				path_buffer.pop();
 			}
@@ -144,14 +101,23 @@ fn initialize(boot_info: &BootInfo) {
 		// Safety: Some modules' initialization functions have orderings that must be guaranteed
 		// to avoid undefined behaviour. These are respected in the below block.
 		unsafe {
+			// The serial port is used for output if there is no framebuffer,
+			// so it is initialized first.
+			serial::initialize();
+
 			gdt::initialize();
 			idt::initialize();
 
-			// The call to `ptr::read` is safe here, as a reference is always valid for reads,
-			// and as `Framebuffer` has no custom destructor and is only comprised of
-			// integers and structs of integers. (and an enum with #[repr(C)])
-			printer::initialize(core::ptr::read(boot_info.framebuffer.as_ref().unwrap()));
-			printer::clear();
+			match boot_info.framebuffer.as_ref() {
+				Some(framebuffer) => {
+					// The call to `ptr::read` is safe here, as a reference is always valid for reads,
+					// and as `Framebuffer` has no custom destructor and is only comprised of
+					// integers and structs of integers. (and an enum with #[repr(C)])
+					printer::initialize(core::ptr::read(framebuffer));
+					printer::clear();
+				}
+				None => println!("No framebuffer found; using the serial port for output"),
+			}
 
 			allocator::initialize(&*boot_info.memory_regions);
 
@@ -166,18 +132,58 @@ fn initialize(boot_info: &BootInfo) {
 	}
 }
 
+#[cfg(test)]
 #[panic_handler]
 fn panic_handler(info: &PanicInfo) -> ! {
+	test::panic_handler(info)
+}
+
+/// Shows what panicked in the middle of a blue screen (and on the serial port), and reboots when a key is pressed.
+#[cfg(not(test))]
+#[panic_handler]
+fn panic_handler(info: &PanicInfo) -> ! {
+	static PANICKING: AtomicBool = AtomicBool::new(false);
+
+	x86_64::instructions::interrupts::disable();
+
+	// If printing the panic panicked, printing it again won't help
+	if PANICKING.swap(true, Ordering::Relaxed) {
+		loop {
+			x86_64::instructions::hlt();
+		}
+	}
+
+	// Formatted into a stack buffer first, without the heap,
+	// so a message which fails to format can't make printing it panic again
 	let loc = info.location().unwrap();
-	match info.message() {
-		Some(message) => {
-			println!("{}: Panic at '{}'", loc, message);
+	let text: SString<512> = match info.message() {
+		Some(message) => SString::format_truncated(format_args!("{}: Panic at '{}'", loc, message)),
+		None => SString::format_truncated(format_args!("{}: Panic", loc)),
+	};
+	let lines = [
+		"Kernel panic!",
+		"",
+		text.as_str(),
+		"",
+		"Press any key to reboot",
+	];
+
+	if printer::is_initialized() {
+		unsafe {
+			printer::clear_with_color(printer::Color::BLUE);
+			printer::print_centered(&lines);
 		}
-		None => {
-			println!("{}: Panic", loc);
+	}
+	if serial::is_initialized() {
+		for line in &lines {
+			serial_println!("{}", line);
 		}
 	}
-	loop {}
+
+	unsafe {
+		ps2::wait_for_key_press();
+		ps2::reset_cpu();
+	}
 }
 
 #[no_mangle]
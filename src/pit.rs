@@ -0,0 +1,52 @@
+use x86_64::instructions::port::Port;
+
+/// The frequency of the PIT's oscillator, in Hz
+const PIT_FREQUENCY: u64 = 1_193_182;
+
+/// 0x42
+static mut CHANNEL_2_DATA_REG: Port<u8> = Port::new(0x42);
+/// 0x43
+static mut MODE_COMMAND_REG: Port<u8> = Port::new(0x43);
+/// 0x61, controls the gate of channel 2 and the PC speaker
+static mut SPEAKER_CONTROL_REG: Port<u8> = Port::new(0x61);
+
+/// Reads the CPU's time stamp counter.
+pub fn read_tsc() -> u64 {
+	unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+/// Measures how many times per second the time stamp counter ticks, using PIT channel 2.
+///
+/// Channel 2 is used as it doesn't need interrupts, which aren't set up for the PIT.
+/// This busy-waits for 10 ms.
+///
+/// # Safety
+///
+/// This should not be called if another call to this function has not yet returned.
+pub unsafe fn measure_tsc_frequency() -> u64 {
+	const MEASURE_DIVISOR: u64 = 100;
+	let count = PIT_FREQUENCY / MEASURE_DIVISOR;
+
+	// Enable the gate of channel 2, but keep the speaker off
+	let control = SPEAKER_CONTROL_REG.read();
+	SPEAKER_CONTROL_REG.write((control & !0x02) | 0x01);
+
+	// Channel 2, low byte then high byte, mode 0 (interrupt on terminal count), binary
+	MODE_COMMAND_REG.write(0b1011_0000);
+	CHANNEL_2_DATA_REG.write(count as u8);
+	CHANNEL_2_DATA_REG.write((count >> 8) as u8);
+
+	// Restart the count by toggling the gate
+	let control = SPEAKER_CONTROL_REG.read();
+	SPEAKER_CONTROL_REG.write(control & !0x01);
+	SPEAKER_CONTROL_REG.write(control | 0x01);
+
+	let start = read_tsc();
+	// Bit 5 is the output of channel 2, which goes high when the count reaches 0
+	while SPEAKER_CONTROL_REG.read() & 0x20 == 0 {}
+	let end = read_tsc();
+
+	SPEAKER_CONTROL_REG.write(control & !0x03);
+
+	(end - start) * MEASURE_DIVISOR
+}
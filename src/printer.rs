@@ -1,4 +1,4 @@
-use bootloader::boot_info::FrameBuffer;
+use bootloader::boot_info::{FrameBuffer, PixelFormat};
 
 use crate::svec::SVec;
 
@@ -33,12 +33,57 @@ const CURSOR_GLYPH: Glyph = [
 
 static mut PRINTER: Printer = unsafe { Printer::uninitialized() };
 
+/// A colour the screen can be filled with
+#[derive(Clone, Copy)]
+pub struct Color {
+	pub red: u8,
+	pub green: u8,
+	pub blue: u8,
+}
+
+impl Color {
+	pub const BLACK: Color = Color {
+		red: 0,
+		green: 0,
+		blue: 0,
+	};
+	pub const BLUE: Color = Color {
+		red: 0,
+		green: 0,
+		blue: 0xAA,
+	};
+
+	/// This colour, lightened towards white by the gray-scale `shade` of a glyph
+	fn lightened(self, shade: u8) -> Self {
+		let lighten = |channel: u8| channel + ((0xFF - channel) as u16 * shade as u16 / 0xFF) as u8;
+		Self {
+			red: lighten(self.red),
+			green: lighten(self.green),
+			blue: lighten(self.blue),
+		}
+	}
+
+	/// The bytes of a pixel of this colour, in `format`
+	fn to_pixel(self, format: PixelFormat) -> [u8; 4] {
+		match format {
+			PixelFormat::RGB => [self.red, self.green, self.blue, 0],
+			PixelFormat::BGR => [self.blue, self.green, self.red, 0],
+			_ => {
+				let gray = (self.red as u16 + self.green as u16 + self.blue as u16) / 3;
+				[gray as u8; 4]
+			}
+		}
+	}
+}
+
 pub struct Printer {
 	framebuffer: FrameBuffer,
 	cursor: (usize, usize),
 	font: &'static [Glyph; 128],
 	initialized: bool,
 	line_lengths: SVec<usize, 128>,
+	/// The colour behind the text
+	background: Color,
 }
 
 impl Printer {
@@ -49,17 +94,21 @@ impl Printer {
 			font: &DEFAULT_FONT,
 			initialized: false,
 			line_lengths: SVec::new(),
+			background: Color::BLACK,
 		}
 	}
 
-	/// Clears the screen by setting every byte to zero.
+	/// Clears the screen by filling it with the background colour.
 	unsafe fn clear(&mut self) {
+		let pixel = self
+			.background
+			.to_pixel(self.framebuffer.info().pixel_format);
 		let (res_x, res_y, stride, bytes_per_pixel, buffer) = self.get_buffer_info();
 		for y in 0..res_y {
 			for x in 0..res_x {
 				let i = (y * stride + x) * bytes_per_pixel;
 				for b in 0..bytes_per_pixel {
-					buffer[i + b] = 0;
+					buffer[i + b] = pixel[b];
 				}
 			}
 		}
@@ -67,14 +116,16 @@ impl Printer {
 		self.cursor = (0, 0);
 	}
 
-	/// Replaces glyph at position with provided glyph
+	/// Replaces glyph at position with provided glyph, drawn on the background colour
 	unsafe fn replace_glyph_at_position(&mut self, glyph: Glyph, position: (usize, usize)) {
+		let background = self.background;
+		let pixel_format = self.framebuffer.info().pixel_format;
 		let (_, _, _, bytes_per_pixel, buffer) = self.get_buffer_info();
 		for y in 0..16 {
 			for x in 0..8 {
-				let color = glyph[y][x];
+				let pixel = background.lightened(glyph[y][x]).to_pixel(pixel_format);
 				for b in 0..bytes_per_pixel {
-					buffer[buffer_offset_to_glyph_position(x, y, position) + b] = color;
+					buffer[buffer_offset_to_glyph_position(x, y, position) + b] = pixel[b];
 				}
 			}
 		}
@@ -161,6 +212,9 @@ impl Printer {
 	///
 	/// TODO: remember offscreen lines for later retrival.
 	fn scroll_down(&mut self) {
+		let background = self
+			.background
+			.to_pixel(self.framebuffer.info().pixel_format);
 		let (res_x, res_y, stride, bytes_per_pixel, buffer) = self.get_buffer_info();
 		for y in 16..res_y {
 			for x in 0..res_x {
@@ -173,7 +227,7 @@ impl Printer {
 		for y in res_y - 16..res_y {
 			for x in 0..res_x {
 				for b in 0..bytes_per_pixel {
-					buffer[(y * stride + x) * bytes_per_pixel + b] = 0;
+					buffer[(y * stride + x) * bytes_per_pixel + b] = background[b];
 				}
 			}
 		}
@@ -238,14 +292,75 @@ pub unsafe fn initialize(framebuffer: FrameBuffer) {
 	PRINTER.initialized = true;
 }
 
-/// Clears the screen by setting every byte in the buffer to 0 and resets the cursor.
+/// Has the printer been initialized?
+pub fn is_initialized() -> bool {
+	unsafe { PRINTER.initialized }
+}
+
+/// The number of columns and rows of text which fit on the screen.
+///
+/// If the printer isn't initialized, text is only written to the serial port,
+/// which is assumed to be 80 by 25.
+pub fn text_size() -> (usize, usize) {
+	if !is_initialized() {
+		return (80, 25);
+	}
+	let info = unsafe { PRINTER.framebuffer.info() };
+	(
+		info.horizontal_resolution / 8,
+		info.vertical_resolution / 16,
+	)
+}
+
+/// Clears the screen to black and resets the cursor.
 pub unsafe fn clear() {
+	clear_with_color(Color::BLACK);
+}
+
+/// Fills the screen with `color` and resets the cursor.
+///
+/// `color` stays the background of all text printed afterwards.
+pub unsafe fn clear_with_color(color: Color) {
 	if !PRINTER.initialized {
 		panic!("PRINTER not initialized!");
 	}
+	PRINTER.background = color;
 	PRINTER.clear();
 }
 
+/// Prints `lines` in the middle of the screen, each line centered on its own.
+///
+/// Lines too long for the screen are split over several rows.
+/// The cursor is left at the start of the row below the last line.
+pub unsafe fn print_centered(lines: &[&str]) {
+	if !PRINTER.initialized {
+		panic!("PRINTER not initialized!");
+	}
+	let (columns, rows) = text_size();
+	let block_rows: usize = lines
+		.iter()
+		.map(|line| ((line.len() + columns - 1) / columns).max(1))
+		.sum();
+	PRINTER.replace_glyph_at_position(EMPTY_GLYPH, PRINTER.cursor);
+	let mut row = rows.saturating_sub(block_rows) / 2;
+	for line in lines {
+		if line.is_empty() {
+			row += 1;
+			continue;
+		}
+		// The glyphs are drawn directly, as printing them would wrap and scroll at the edges of the screen
+		for chunk in line.as_bytes().chunks(columns) {
+			let first_column = (columns - chunk.len()) / 2;
+			for (i, &char) in chunk.iter().enumerate() {
+				let glyph = PRINTER.font[char.min(0x7F) as usize];
+				PRINTER.replace_glyph_at_position(glyph, (first_column + i, row.min(rows - 1)));
+			}
+			row += 1;
+		}
+	}
+	PRINTER.cursor = (0, row.min(rows - 1));
+}
+
 /// Use `print!()` macro or `print_str` instead.
 /*pub unsafe fn print_char(char: char) {
 		PRINTER.print_char(char);
@@ -283,10 +398,16 @@ macro_rules! println {
     ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
 }
 
+/// Prints to the screen, and mirrors the output to the serial port.
 #[doc(hidden)]
 pub fn _print(args: core::fmt::Arguments) {
 	use core::fmt::Write;
-	unsafe {
-		PRINTER.write_fmt(args).unwrap();
+	if is_initialized() {
+		unsafe {
+			PRINTER.write_fmt(args).unwrap();
+		}
+	}
+	if crate::serial::is_initialized() {
+		crate::serial::_print(args);
 	}
 }
@@ -116,6 +116,30 @@ pub unsafe fn get_byte() -> u8 {
 	DRIVER.read_data()
 }
 
+/// Waits until a key is pressed, by polling the controller.
+///
+/// Works with interrupts disabled, as long as the keyboard's interrupts are not handled.
+/// Bytes already waiting in the controller are thrown away first, so a key released just before
+/// this call doesn't count. Only scancode set 1 is supported, where bit 7 is set for releases and prefixes.
+pub unsafe fn wait_for_key_press() {
+	while DRIVER.status_command_port.read() & 1 != 0 {
+		DRIVER.data_port.read();
+	}
+	while DRIVER.read_data() & 0x80 != 0 {}
+}
+
+/// Reboots the computer, by pulsing the CPU reset line through the PS/2 controller.
+///
+/// Halts forever if the controller doesn't reset the CPU.
+pub unsafe fn reset_cpu() -> ! {
+	// While bit 1 (input buffer full) is set, wait
+	while DRIVER.status_command_port.read() & 0b10 != 0 {}
+	DRIVER.send_command(0xFE);
+	loop {
+		x86_64::instructions::hlt();
+	}
+}
+
 extern "x86-interrupt" fn default_handler(stack_frame: InterruptStackFrame) {
 	println!("Default handler");
 
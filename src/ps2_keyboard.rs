@@ -81,8 +81,11 @@ impl Driver {
 						handle_scancode_helper(self, svec, byte);
 					}
 					_ => {
-						svec.push(byte);
-						panic!("Unrecognized byte sequence {:#X?}", svec.get_slice());
+						// Unrecognized byte sequence.
+						// `handle_scancode` turns it into a `KeyCode::Unknown` event,
+						// and the state is reset so the next byte starts a new keypress.
+						let svec = svec.clone();
+						handle_scancode_helper(self, svec, byte);
 					}
 				}
 			}
@@ -90,6 +93,8 @@ impl Driver {
 	}
 
 	/// Converts a scancode into a `KeyCode`.
+	///
+	/// Unrecognized scancodes are reported as `KeyCode::Unknown`.
 	fn handle_scancode(&mut self, scancode: &mut [u8]) {
 		let was_released = match scancode {
 			[b] | [0xE0, b] => {
@@ -233,7 +238,7 @@ impl Driver {
 			[0xE0, 0x6D] => KeyCode::Unknown,   // media select
 			[0xE0, 0x2A, 0xE0, 0x37] => KeyCode::PrintScreen,
 			[0xE1, 0x1D, 0x45, 0xE1, 0x9D, 0xC5] => KeyCode::PauseBreak,
-			_ => panic!("Unrecognized keycode"),
+			_ => KeyCode::Unknown,
 		};
 
 		let held = self.is_pressed(keycode);
@@ -281,11 +286,17 @@ impl Driver {
 	/// Translates a printable `KeyCode` to it's corrosponding `char`
 	/// `None` if no such char exists.
 	/// Uses Swedish keyboard layout, except for NumpadDecimal `,` -> `.`
+	///
+	/// Always `None` if Ctrl, Alt or Meta is held, as those combinations are shortcuts.
 	fn translate_keycode(&self, keycode: KeyCode, modifiers: Modifiers) -> Option<char> {
 		const NONE: Modifiers = Modifiers::NONE;
 		const SHIFT: Modifiers = Modifiers::SHIFT;
 		const ALTGR: Modifiers = Modifiers::ALTGR;
 
+		if modifiers.ctrl || modifiers.alt || modifiers.meta {
+			return None;
+		}
+
 		Some(match (keycode, modifiers) {
 			(KeyCode::Paragraph, NONE) => '§',
 			(KeyCode::Digit1, NONE) => '1',
@@ -681,3 +692,25 @@ extern "x86-interrupt" fn interrupt_handler(_: InterruptStackFrame) {
 
 	unsafe { crate::pic::send_eoi(1) };
 }
+
+#[cfg(test)]
+mod tests {
+	use super::{Driver, KeyCode, Modifiers};
+
+	#[test_case]
+	fn shortcuts_do_not_translate_to_chars() {
+		let driver = Driver::new();
+		assert_eq!(
+			driver.translate_keycode(KeyCode::S, Modifiers::NONE),
+			Some('s')
+		);
+		assert_eq!(driver.translate_keycode(KeyCode::S, Modifiers::CTRL), None);
+		assert_eq!(driver.translate_keycode(KeyCode::S, Modifiers::ALT), None);
+		assert_eq!(driver.translate_keycode(KeyCode::S, Modifiers::META), None);
+		let ctrl_shift = Modifiers {
+			shift: true,
+			..Modifiers::CTRL
+		};
+		assert_eq!(driver.translate_keycode(KeyCode::S, ctrl_shift), None);
+	}
+}
@@ -0,0 +1,107 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use x86_64::instructions::port::{Port, PortReadOnly};
+
+// Assuming the "typical" port for COM1
+const COM1_BASE_PORT: u16 = 0x3F8;
+/// 0x3F8 (0x3F9 when DLAB is set)
+static mut DATA_REG: Port<u8> = Port::new(COM1_BASE_PORT);
+/// 0x3F9
+static mut INTERRUPT_ENABLE_REG: Port<u8> = Port::new(COM1_BASE_PORT + 1);
+/// 0x3FA
+static mut FIFO_CONTROL_REG: Port<u8> = Port::new(COM1_BASE_PORT + 2);
+/// 0x3FB
+static mut LINE_CONTROL_REG: Port<u8> = Port::new(COM1_BASE_PORT + 3);
+/// 0x3FC
+static mut MODEM_CONTROL_REG: Port<u8> = Port::new(COM1_BASE_PORT + 4);
+/// 0x3FD
+static mut LINE_STATUS_REG: PortReadOnly<u8> = PortReadOnly::new(COM1_BASE_PORT + 5);
+
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+/// Initializes the COM1 serial port.
+///
+/// Uses 38400 baud, 8 data bits, no parity and one stop bit.
+///
+/// # Safety
+///
+/// This should not be called if another call to this function has not yet returned.
+pub unsafe fn initialize() {
+	// Disable interrupts
+	INTERRUPT_ENABLE_REG.write(0x00);
+	// Enable DLAB, so the divisor can be set
+	LINE_CONTROL_REG.write(0x80);
+	// Divisor 3 (38400 baud), low byte then high byte
+	DATA_REG.write(0x03);
+	INTERRUPT_ENABLE_REG.write(0x00);
+	// 8 bits, no parity, one stop bit, DLAB disabled
+	LINE_CONTROL_REG.write(0x03);
+	// Enable and clear FIFO, with a 14-byte threshold
+	FIFO_CONTROL_REG.write(0xC7);
+	// Data terminal ready, request to send
+	MODEM_CONTROL_REG.write(0x03);
+
+	INITIALIZED.store(true, Ordering::Release);
+}
+
+/// Has the serial port been initialized?
+pub fn is_initialized() -> bool {
+	INITIALIZED.load(Ordering::Acquire)
+}
+
+/// Sends a single byte over the serial port, waiting until the port is ready for it.
+unsafe fn write_byte(byte: u8) {
+	// While bit 5 (transmitter holding register empty) is not set, wait
+	while LINE_STATUS_REG.read() & 0x20 == 0 {}
+	DATA_REG.write(byte);
+}
+
+/// Sends the input string over the serial port.
+///
+/// Newlines are sent as `"\r\n"`.
+pub unsafe fn print_str(string: &str) {
+	if !is_initialized() {
+		panic!("Serial port not initialized!");
+	}
+	for byte in string.bytes() {
+		if byte == b'\n' {
+			write_byte(b'\r');
+		}
+		write_byte(byte);
+	}
+}
+
+struct SerialWriter;
+
+impl core::fmt::Write for SerialWriter {
+	fn write_str(&mut self, s: &str) -> core::fmt::Result {
+		unsafe {
+			print_str(s);
+		}
+		Ok(())
+	}
+}
+
+/// Prints to the serial port only.
+///
+/// `print!` already mirrors everything to the serial port,
+/// so this is only needed for output which shouldn't be shown on the screen.
+#[macro_export]
+macro_rules! serial_print {
+    ($($arg:tt)*) => ($crate::serial::_print(format_args!($($arg)*)));
+}
+
+/// Prints to the serial port only, with a newline.
+///
+/// See [serial_print].
+#[macro_export]
+macro_rules! serial_println {
+    () => ($crate::serial_print!("\n"));
+    ($($arg:tt)*) => ($crate::serial_print!("{}\n", format_args!($($arg)*)));
+}
+
+#[doc(hidden)]
+pub fn _print(args: core::fmt::Arguments) {
+	use core::fmt::Write;
+	SerialWriter.write_fmt(args).unwrap();
+}
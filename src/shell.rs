@@ -0,0 +1,549 @@
+//! The command line shell.
+//!
+//! A command is a name, optionally followed by a space and its arguments.
+//! Commands are looked up in [`COMMANDS`], so adding a command is just adding an entry there.
+
+use alloc::{format, string::String};
+
+use crate::{
+	harddisk::{self, fat32::FatError},
+	pit, ps2, viewer,
+};
+
+/// A command that can be run from the shell
+struct Command {
+	name: &'static str,
+	/// Short description, shown by `help`
+	description: &'static str,
+	/// Called with everything after the first space, or the empty slice if there is none
+	handler: unsafe fn(args: &[u8]),
+}
+
+/// All commands, in the order `help` lists them
+const COMMANDS: &[Command] = &[
+	Command {
+		name: "help",
+		description: "List all commands",
+		handler: help,
+	},
+	Command {
+		name: "read",
+		description: "read <path>: List a directory, or print a file",
+		handler: read,
+	},
+	Command {
+		name: "view",
+		description: "view <path>: Scroll through a file of any size",
+		handler: view,
+	},
+	Command {
+		name: "create",
+		description: "create <path>: Create an empty file",
+		handler: create,
+	},
+	Command {
+		name: "write",
+		description: "write <path>: Write a test text to a file",
+		handler: write,
+	},
+	Command {
+		name: "mkdir",
+		description: "mkdir <path>: Create a directory",
+		handler: mkdir,
+	},
+	Command {
+		name: "delete",
+		description: "delete <path>: Delete a file",
+		handler: delete,
+	},
+	Command {
+		name: "rmdir",
+		description: "rmdir <path>: Delete an empty directory",
+		handler: rmdir,
+	},
+	Command {
+		name: "wc",
+		description: "wc <path>: Count the lines, words and bytes in a file",
+		handler: wc,
+	},
+	Command {
+		name: "grep",
+		description: "grep <pattern> <dir>: Print the lines containing pattern in all files in dir",
+		handler: grep,
+	},
+	Command {
+		name: "layout",
+		description: "layout <path>: Show which clusters a file is stored in",
+		handler: layout,
+	},
+	Command {
+		name: "benchdisk",
+		description: "Measure how fast the disk is",
+		handler: benchdisk,
+	},
+	Command {
+		name: "df",
+		description: "Show how much of the disk is used",
+		handler: df,
+	},
+	Command {
+		name: "parts",
+		description: "List the partitions on the disk",
+		handler: parts,
+	},
+	Command {
+		name: "test",
+		description: "Create 32 files in EFI",
+		handler: test,
+	},
+	Command {
+		name: "mount",
+		description: "mount <index>: Use the file system on another partition, see parts",
+		handler: mount,
+	},
+	Command {
+		name: "rescan",
+		description: "Find the disk, its partitions and its file system again",
+		handler: rescan,
+	},
+	Command {
+		name: "sync",
+		description: "Write all buffered changes to disk",
+		handler: sync,
+	},
+	Command {
+		name: "reboot",
+		description: "Write all buffered changes to disk, and reboot",
+		handler: reboot,
+	},
+	Command {
+		name: "halt",
+		description: "Write all buffered changes to disk, and stop the computer",
+		handler: halt,
+	},
+];
+
+/// Runs the command on `line`, or tells the user there is no such command.
+///
+/// # Safety
+///
+/// Commands use the file system, so this may not be called before [`harddisk::initialize`].
+pub unsafe fn run(line: &[u8]) {
+	let (name, args) = split_command(line);
+	match COMMANDS.iter().find(|c| c.name.as_bytes() == name) {
+		Some(command) => (command.handler)(args),
+		None => println!(
+			"Unrecognized command '{}', type 'help' to list all commands",
+			String::from_utf8_lossy(name)
+		),
+	}
+}
+
+/// Splits `line` at its first space, into the name of the command and its arguments.
+fn split_command(line: &[u8]) -> (&[u8], &[u8]) {
+	match line.iter().position(|&b| b == b' ') {
+		Some(index) => (&line[..index], &line[index + 1..]),
+		None => (line, &[]),
+	}
+}
+
+unsafe fn help(_: &[u8]) {
+	for command in COMMANDS {
+		println!("{:10}  {}", command.name, command.description);
+	}
+}
+
+unsafe fn read(path: &[u8]) {
+	match harddisk::fat32::list_entries(path) {
+		Ok(e) => {
+			for e in e {
+				let modified = e
+					.modified
+					.map_or(String::new(), |modified| format!("{}", modified));
+				println!(
+					"{:12}  {:3}  {:19}  {}",
+					e.display_name(),
+					if e.is_directory { "DIR" } else { "   " },
+					modified,
+					e.size
+				);
+			}
+		}
+		Err(FatError::IsntDirectory) => match harddisk::fat32::read_file_to_vec(path) {
+			Ok(data) => {
+				println!("{}", String::from_utf8_lossy(&data));
+			}
+			Err(e) => println!("Error: {:#?}", e),
+		},
+		Err(e) => {
+			println!("Error: {:#?}", e)
+		}
+	}
+}
+
+unsafe fn view(path: &[u8]) {
+	if let Err(e) = viewer::view(path) {
+		println!("Error: {:#?}", e);
+	}
+}
+
+unsafe fn create(path: &[u8]) {
+	match harddisk::fat32::create_empty_file(path) {
+		Ok(info) => println!("{:#?}", info),
+		Err(e) => println!("Error: {:#?}", e),
+	}
+}
+
+unsafe fn write(path: &[u8]) {
+	let data_to_write = include_bytes!("../file_to_write.txt");
+	match harddisk::fat32::write_file(path, data_to_write) {
+		Ok(_) => {}
+		Err(e) => println!("Error: {:#?}", e),
+	}
+}
+
+unsafe fn mkdir(path: &[u8]) {
+	if let Err(e) = harddisk::fat32::create_directory(path) {
+		println!("Error: {:#?}", e);
+	}
+}
+
+unsafe fn delete(path: &[u8]) {
+	if let Err(e) = harddisk::fat32::delete_file(path) {
+		println!("Error: {:#?}", e);
+	}
+}
+
+unsafe fn rmdir(path: &[u8]) {
+	if let Err(e) = harddisk::fat32::remove_directory(path) {
+		println!("Error: {:#?}", e);
+	}
+}
+
+unsafe fn wc(path: &[u8]) {
+	match count_file(path) {
+		Ok((lines, words, bytes)) => println!("{} {} {}", lines, words, bytes),
+		Err(e) => println!("Error: {:#?}", e),
+	}
+}
+
+unsafe fn grep(args: &[u8]) {
+	// The pattern may contain spaces, so the directory is everything after the last one
+	let (pattern, directory_path) = match args.iter().rposition(|&b| b == b' ') {
+		Some(index) => (&args[..index], &args[index + 1..]),
+		None => (args, &[][..]),
+	};
+	if let Err(e) = grep_directory(pattern, directory_path) {
+		println!("Error: {:#?}", e);
+	}
+}
+
+unsafe fn layout(path: &[u8]) {
+	match harddisk::fat32::file_layout(path) {
+		Ok(clusters) => print_layout(&clusters),
+		Err(e) => println!("Error: {:#?}", e),
+	}
+}
+
+unsafe fn benchdisk(_: &[u8]) {
+	match benchmark_disk() {
+		Ok((write_speed, read_speed)) => println!(
+			"Wrote {} KiB at {} KiB/s, read it back at {} KiB/s",
+			BENCHMARK_SIZE / 1024,
+			write_speed / 1024,
+			read_speed / 1024
+		),
+		Err(e) => println!("Error: {:#?}", e),
+	}
+}
+
+unsafe fn df(_: &[u8]) {
+	let (free, total) = harddisk::fat32::free_space();
+	println!(
+		"Used {} KiB, free {} KiB, total {} KiB",
+		(total - free) / 1024,
+		free / 1024,
+		total / 1024
+	);
+}
+
+unsafe fn parts(_: &[u8]) {
+	for partition in harddisk::partitions::list_partitions() {
+		print!(
+			"{}: type {} ({}), guid {}, sectors {}..{}, name '",
+			partition.index(),
+			partition.type_name(),
+			partition.partition_type_guid(),
+			partition.partition_guid(),
+			partition.start_sector(),
+			partition.start_sector() + partition.sector_count()
+		);
+		for c in partition.name().get_slice() {
+			print!("{}", c);
+		}
+		println!("'");
+	}
+}
+
+unsafe fn test(_: &[u8]) {
+	for i in 0..32 {
+		println!("Creating file {}", i);
+		match harddisk::fat32::write_file_deferred(
+			&harddisk::fat32::path(&format!("EFI/{}", i)),
+			format!("File number {}\n", i).as_bytes(),
+		) {
+			Ok(_) => {}
+			Err(e) => println!("Error: {:#?}", e),
+		}
+	}
+	if let Err(e) = harddisk::fat32::sync() {
+		println!("Error: {:#?}", e);
+	}
+}
+
+unsafe fn mount(args: &[u8]) {
+	let index = core::str::from_utf8(args)
+		.ok()
+		.and_then(|index| index.parse().ok());
+	match index {
+		Some(index) => match harddisk::fat32::mount(index) {
+			Ok(()) => println!("Mounted partition {}", index),
+			Err(e) => println!("Error: {:#?}", e),
+		},
+		None => println!(
+			"Usage: mount <index>, partition {} is mounted",
+			harddisk::fat32::mounted_partition()
+		),
+	}
+}
+
+unsafe fn rescan(_: &[u8]) {
+	if let Err(e) = harddisk::rescan() {
+		println!("Error: {:#?}", e);
+	}
+}
+
+unsafe fn sync(_: &[u8]) {
+	if let Err(e) = harddisk::fat32::sync() {
+		println!("Error: {:#?}", e);
+	}
+}
+
+unsafe fn reboot(_: &[u8]) {
+	if let Err(e) = harddisk::fat32::sync() {
+		println!("Error: {:#?}, not rebooting", e);
+		return;
+	}
+	ps2::reset_cpu();
+}
+
+unsafe fn halt(_: &[u8]) {
+	if let Err(e) = harddisk::fat32::sync() {
+		println!("Error: {:#?}, not halting", e);
+		return;
+	}
+	println!("It is now safe to turn off the computer");
+	x86_64::instructions::interrupts::disable();
+	loop {
+		x86_64::instructions::hlt();
+	}
+}
+
+/// Counts the lines, words and bytes in the file at `path`, like `wc`.
+///
+/// The file is read one sector at a time, so it never has to fit in memory.
+/// Words are separated by ASCII whitespace, which never occurs inside a multi-byte UTF-8 character,
+/// so invalid UTF-8 is counted just fine.
+unsafe fn count_file(path: &[u8]) -> Result<(usize, usize, usize), FatError> {
+	let file = harddisk::fat32::open(path)?;
+	let mut buffer = [0; 512];
+	let mut lines = 0;
+	let mut words = 0;
+	let mut in_word = false;
+	let mut offset = 0;
+	while offset < file.len() {
+		let read = file.read_at(offset, &mut buffer)?;
+		if read == 0 {
+			break;
+		}
+		for &byte in &buffer[..read] {
+			if byte == b'\n' {
+				lines += 1;
+			}
+			if byte.is_ascii_whitespace() {
+				in_word = false;
+			} else if !in_word {
+				in_word = true;
+				words += 1;
+			}
+		}
+		offset += read;
+	}
+	file.close()?;
+	Ok((lines, words, offset))
+}
+
+/// Prints the clusters of a file as ranges of consecutive clusters, like `5-10, 20, 30-31`.
+fn print_layout(clusters: &[u32]) {
+	let mut fragments = 0;
+	let mut i = 0;
+	while i < clusters.len() {
+		let start = clusters[i];
+		while i + 1 < clusters.len() && clusters[i + 1] == clusters[i] + 1 {
+			i += 1;
+		}
+		if fragments > 0 {
+			print!(", ");
+		}
+		if clusters[i] == start {
+			print!("{}", start);
+		} else {
+			print!("{}-{}", start, clusters[i]);
+		}
+		fragments += 1;
+		i += 1;
+	}
+	println!();
+	println!("{} clusters in {} fragments", clusters.len(), fragments);
+}
+
+/// Prints `path:line: text` for every line containing `pattern`,
+/// in all files in the directory at `directory_path` and its subdirectories.
+unsafe fn grep_directory(pattern: &[u8], directory_path: &[u8]) -> Result<(), FatError> {
+	for entry in harddisk::fat32::list_entries(directory_path)? {
+		let name = entry.name.get_slice();
+		if name == b"." || name == b".." {
+			continue;
+		}
+		let path = entry.path_in(directory_path);
+		if entry.is_directory {
+			grep_directory(pattern, &path)?;
+		} else {
+			grep_file(pattern, &path)?;
+		}
+	}
+	Ok(())
+}
+
+/// Prints `path:line: text` for every line in the file at `path` containing `pattern`.
+///
+/// The file is read one sector at a time, and only the current line is kept in memory.
+/// Lines are split at `\n`, which never occurs inside a multi-byte UTF-8 character,
+/// and invalid UTF-8 is printed as replacement characters.
+unsafe fn grep_file(pattern: &[u8], path: &[u8]) -> Result<(), FatError> {
+	let file = harddisk::fat32::open(path)?;
+	let mut buffer = [0; 512];
+	let mut line = alloc::vec::Vec::new();
+	let mut line_number = 1;
+	let mut offset = 0;
+
+	let print_if_matching = |line: &[u8], line_number: usize| {
+		let line = line.strip_suffix(b"\r").unwrap_or(line);
+		if contains(line, pattern) {
+			println!(
+				"{}:{}: {}",
+				String::from_utf8_lossy(path),
+				line_number,
+				String::from_utf8_lossy(line)
+			);
+		}
+	};
+
+	while offset < file.len() {
+		let read = file.read_at(offset, &mut buffer)?;
+		if read == 0 {
+			break;
+		}
+		for &byte in &buffer[..read] {
+			if byte == b'\n' {
+				print_if_matching(&line, line_number);
+				line.clear();
+				line_number += 1;
+			} else {
+				line.push(byte);
+			}
+		}
+		offset += read;
+	}
+	if !line.is_empty() {
+		print_if_matching(&line, line_number);
+	}
+	file.close()
+}
+
+/// Does `haystack` contain `needle`? The empty needle is contained in everything.
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+	needle.is_empty()
+		|| haystack
+			.windows(needle.len())
+			.any(|window| window == needle)
+}
+
+/// How many bytes `benchmark_disk` writes and reads
+const BENCHMARK_SIZE: usize = 64 * 1024;
+
+/// Measures how fast the disk can be written to and read from, in bytes per second.
+///
+/// All I/O is done in the file `BENCH.TMP`, so the rest of the file system is never touched.
+unsafe fn benchmark_disk() -> Result<(u64, u64), FatError> {
+	const PATH: &[u8] = b"BENCH.TMP";
+
+	match harddisk::fat32::create_empty_file(PATH) {
+		Ok(_) | Err(FatError::AlreadyExists) => {}
+		Err(e) => return Err(e),
+	}
+	let tsc_frequency = pit::measure_tsc_frequency();
+	let bytes_per_second = |cycles: u64| BENCHMARK_SIZE as u64 * tsc_frequency / cycles.max(1);
+
+	let data = [0xAA; 512];
+	let mut file = harddisk::fat32::open(PATH)?;
+	let start = pit::read_tsc();
+	let mut offset = 0;
+	while offset < BENCHMARK_SIZE {
+		offset += file.write_at(offset, &data)?;
+	}
+	file.close()?;
+	let write_cycles = pit::read_tsc() - start;
+
+	let mut buffer = [0; 512];
+	let file = harddisk::fat32::open(PATH)?;
+	let start = pit::read_tsc();
+	let mut offset = 0;
+	while offset < BENCHMARK_SIZE {
+		match file.read_at(offset, &mut buffer)? {
+			0 => break,
+			read => offset += read,
+		}
+	}
+	file.close()?;
+	let read_cycles = pit::read_tsc() - start;
+
+	Ok((
+		bytes_per_second(write_cycles),
+		bytes_per_second(read_cycles),
+	))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{contains, split_command};
+
+	#[test_case]
+	fn contains_finds_substrings() {
+		assert!(contains(b"hello world", b"o w"));
+		assert!(contains(b"hello", b"hello"));
+		assert!(contains(b"hello", b""));
+		assert!(!contains(b"hello", b"hello!"));
+		assert!(!contains(b"", b"a"));
+	}
+
+	#[test_case]
+	fn commands_are_split_at_first_space() {
+		assert_eq!(
+			split_command(b"read EFI>BOOT"),
+			(&b"read"[..], &b"EFI>BOOT"[..])
+		);
+		assert_eq!(split_command(b"grep a b c"), (&b"grep"[..], &b"a b c"[..]));
+		assert_eq!(split_command(b"parts"), (&b"parts"[..], &b""[..]));
+	}
+}
@@ -0,0 +1,184 @@
+use core::fmt::{Arguments, Debug, Display, Write};
+
+use crate::svec::SVec;
+
+/// Static String, a UTF-8 string of at most `N` bytes which needs no dynamic memory allocation.
+///
+/// Always contains valid UTF-8, as it can only be built from `char`s and `str`s.
+pub struct SString<const N: usize> {
+	inner: SVec<u8, N>,
+}
+
+impl<const N: usize> SString<N> {
+	/// Creates a new empty SString.
+	pub const fn new() -> Self {
+		Self { inner: SVec::new() }
+	}
+
+	/// The length of the string, in bytes.
+	pub fn len(&self) -> usize {
+		self.inner.len()
+	}
+
+	/// Appends `c`, encoded as UTF-8.
+	///
+	/// Fails without changing the string if there isn't room for all of its bytes.
+	pub fn push(&mut self, c: char) -> Result<(), ()> {
+		let mut buffer = [0; 4];
+		self.push_str(c.encode_utf8(&mut buffer))
+	}
+
+	/// Appends `s`.
+	///
+	/// Fails without changing the string if there isn't room for all of it.
+	pub fn push_str(&mut self, s: &str) -> Result<(), ()> {
+		if self.len() + s.len() > N {
+			return Err(());
+		}
+		for b in s.bytes() {
+			self.inner.push(b);
+		}
+		Ok(())
+	}
+
+	/// Removes the last `char`, and returns it.
+	pub fn pop(&mut self) -> Option<char> {
+		let c = self.as_str().chars().next_back()?;
+		for _ in 0..c.len_utf8() {
+			self.inner.pop();
+		}
+		Some(c)
+	}
+
+	/// Formats `args` into a new string, cutting off whatever doesn't fit (at a `char` boundary).
+	///
+	/// Unlike `write!`, this never fails, and unlike `format!` it needs no heap,
+	/// so it can be used before the allocator is initialized, or while panicking.
+	/// Formatting stops quietly if a `Display` implementation fails.
+	pub fn format_truncated(args: Arguments) -> Self {
+		/// Writes whole `char`s until one doesn't fit, then drops the rest
+		struct Truncating<'a, const N: usize>(&'a mut SString<N>);
+
+		impl<'a, const N: usize> Write for Truncating<'a, N> {
+			fn write_str(&mut self, s: &str) -> core::fmt::Result {
+				for c in s.chars() {
+					if self.0.push(c).is_err() {
+						// Stops the formatting, the error is ignored below
+						return Err(core::fmt::Error);
+					}
+				}
+				Ok(())
+			}
+		}
+
+		let mut s = Self::new();
+		let _ = Truncating(&mut s).write_fmt(args);
+		s
+	}
+
+	pub fn as_str(&self) -> &str {
+		self.inner.to_str()
+	}
+}
+
+impl<const N: usize> Clone for SString<N> {
+	fn clone(&self) -> Self {
+		Self {
+			inner: self.inner.clone(),
+		}
+	}
+}
+
+impl<const N: usize> PartialEq<str> for SString<N> {
+	fn eq(&self, other: &str) -> bool {
+		self.as_str() == other
+	}
+}
+
+impl<const N: usize> PartialEq<&str> for SString<N> {
+	fn eq(&self, other: &&str) -> bool {
+		self.as_str() == *other
+	}
+}
+
+impl<const N: usize> Display for SString<N> {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		write!(f, "{}", self.as_str())
+	}
+}
+
+impl<const N: usize> Debug for SString<N> {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		write!(f, "{:?}", self.as_str())
+	}
+}
+
+/// Lets `write!` be used to format into the string.
+/// Fails if the formatted text doesn't fit, in which case the string contains what did fit.
+impl<const N: usize> Write for SString<N> {
+	fn write_str(&mut self, s: &str) -> core::fmt::Result {
+		self.push_str(s).map_err(|_| core::fmt::Error)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use core::fmt::Write;
+
+	use super::SString;
+
+	#[test_case]
+	fn push_multi_byte_chars() {
+		let mut s: SString<8> = SString::new();
+		s.push('a').unwrap();
+		s.push('å').unwrap();
+		s.push('€').unwrap();
+		assert_eq!(s.len(), 6);
+		assert!(s == "aå€");
+		assert_eq!(s.pop(), Some('€'));
+		assert!(s == "aå");
+	}
+
+	#[test_case]
+	fn capacity_is_enforced() {
+		let mut s: SString<4> = SString::new();
+		s.push_str("abc").unwrap();
+		// 'å' is two bytes, which doesn't fit
+		assert!(s.push('å').is_err());
+		assert!(s.push_str("de").is_err());
+		assert!(s == "abc");
+		s.push('d').unwrap();
+		assert!(s.push('e').is_err());
+	}
+
+	#[test_case]
+	fn write_formats_into_string() {
+		let mut s: SString<16> = SString::new();
+		write!(s, "{}>{}", "EFI", 12).unwrap();
+		assert!(s == "EFI>12");
+		assert!(write!(s, "{}", "far too long to fit").is_err());
+	}
+
+	#[test_case]
+	fn format_truncated_cuts_off_at_chars() {
+		let s: SString<16> = SString::format_truncated(format_args!("{}: {}", "panic", 1234));
+		assert!(s == "panic: 1234");
+		let s: SString<8> = SString::format_truncated(format_args!("{}", "far too long"));
+		assert!(s == "far too ");
+		// 'å' is two bytes, and only one is left
+		let s: SString<4> = SString::format_truncated(format_args!("abcå"));
+		assert!(s == "abc");
+	}
+
+	#[test_case]
+	fn format_truncated_stops_at_failing_display() {
+		struct Failing;
+		impl core::fmt::Display for Failing {
+			fn fmt(&self, _: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+				Err(core::fmt::Error)
+			}
+		}
+		let s: SString<16> = SString::format_truncated(format_args!("ok {} not", Failing));
+		assert!(s == "ok ");
+	}
+}
@@ -86,6 +86,41 @@ impl<T, const N: usize> SVec<T, N> {
 	pub fn get_slice_mut(&mut self) -> &mut [T] {
 		unsafe { core::mem::transmute(&mut self.inner[..self.length]) }
 	}
+
+	/// Does the SVec contain `value`?
+	pub fn contains(&self, value: &T) -> bool
+	where T: PartialEq {
+		self.get_slice().contains(value)
+	}
+
+	/// Returns the index of the first object for which `predicate` returns `true`.
+	pub fn position(&self, predicate: impl FnMut(&T) -> bool) -> Option<usize> {
+		self.get_slice().iter().position(predicate)
+	}
+
+	/// Iterates over the objects, from the last added to the first.
+	pub fn iter_rev(&self) -> core::iter::Rev<core::slice::Iter<'_, T>> {
+		self.get_slice().iter().rev()
+	}
+
+	/// Divides the objects into two slices at `mid`, the first containing the objects before it.
+	///
+	/// # Panics
+	///
+	/// Panics if `mid` is larger than the length.
+	pub fn split_at(&self, mid: usize) -> (&[T], &[T]) {
+		self.get_slice().split_at(mid)
+	}
+
+	/// Iterates over the objects in slices of `chunk_size` objects.
+	/// The last slice is shorter if the length isn't a multiple of `chunk_size`.
+	///
+	/// # Panics
+	///
+	/// Panics if `chunk_size` is 0.
+	pub fn chunks(&self, chunk_size: usize) -> core::slice::Chunks<'_, T> {
+		self.get_slice().chunks(chunk_size)
+	}
 }
 
 impl<T, const N: usize> Index<usize> for SVec<T, N> {
@@ -122,6 +157,33 @@ impl<T, const N: usize> IndexMut<usize> for SVec<T, N> {
 	}
 }
 
+/// Compares the initialized objects, so SVecs of different capacities can be equal
+impl<T: PartialEq, const N: usize, const M: usize> PartialEq<SVec<T, M>> for SVec<T, N> {
+	fn eq(&self, other: &SVec<T, M>) -> bool {
+		self.get_slice() == other.get_slice()
+	}
+}
+
+impl<T: Eq, const N: usize> Eq for SVec<T, N> {}
+
+impl<T: PartialEq, const N: usize> PartialEq<[T]> for SVec<T, N> {
+	fn eq(&self, other: &[T]) -> bool {
+		self.get_slice() == other
+	}
+}
+
+impl<T: PartialEq, const N: usize> PartialEq<&[T]> for SVec<T, N> {
+	fn eq(&self, other: &&[T]) -> bool {
+		self.get_slice() == *other
+	}
+}
+
+impl<T: PartialEq, const N: usize, const M: usize> PartialEq<[T; M]> for SVec<T, N> {
+	fn eq(&self, other: &[T; M]) -> bool {
+		self.get_slice() == &other[..]
+	}
+}
+
 impl<T: Clone, const N: usize> Clone for SVec<T, N> {
 	/// Clones this SVec by making a new SVec and pushing a clone of each item one-by-one.
 	fn clone(&self) -> Self {
@@ -212,3 +274,76 @@ impl<const N: usize> Display for SVec<u8, N> {
 		write!(f, "{}", self.to_str())
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use core::convert::TryFrom;
+
+	use super::SVec;
+
+	#[test_case]
+	fn push_and_pop() {
+		let mut svec: SVec<u8, 4> = SVec::new();
+		svec.push(1);
+		svec.push(2);
+		assert_eq!(svec.len(), 2);
+		assert_eq!(svec.pop(), Some(2));
+		assert_eq!(svec.pop(), Some(1));
+		assert_eq!(svec.pop(), None);
+	}
+
+	#[test_case]
+	fn remove_shifts_elements() {
+		let mut svec: SVec<u8, 4> = SVec::try_from(&[1, 2, 3, 4][..]).unwrap();
+		assert_eq!(svec.remove(1), 2);
+		assert_eq!(svec.get_slice(), &[1, 3, 4]);
+	}
+
+	#[test_case]
+	fn queries() {
+		let svec: SVec<u8, 4> = SVec::try_from(&[1, 2, 3, 2][..]).unwrap();
+		assert!(svec.contains(&3));
+		assert!(!svec.contains(&4));
+		assert_eq!(svec.position(|&v| v == 2), Some(1));
+		assert_eq!(svec.position(|&v| v == 4), None);
+		assert!(svec.iter_rev().eq([2, 3, 2, 1].iter()));
+	}
+
+	#[test_case]
+	fn equality() {
+		let svec: SVec<u8, 4> = SVec::try_from(&b"abc"[..]).unwrap();
+		let same: SVec<u8, 8> = SVec::try_from(&b"abc"[..]).unwrap();
+		let prefix: SVec<u8, 4> = SVec::try_from(&b"ab"[..]).unwrap();
+		assert!(svec == same);
+		assert!(svec != prefix);
+		assert!(svec == b"abc"[..]);
+		assert!(svec == &b"abc"[..]);
+		assert!(svec == *b"abc");
+		assert!(svec != &b"ab"[..]);
+		assert!(svec != &b"abcd"[..]);
+		assert!(svec != *b"abd");
+	}
+
+	#[test_case]
+	fn try_from_too_long_slice() {
+		assert!(SVec::<u8, 2>::try_from(&[1, 2, 3][..]).is_err());
+	}
+
+	#[test_case]
+	fn split_at_middle_and_ends() {
+		let svec: SVec<u8, 8> = SVec::try_from(&[1, 2, 3, 4, 5][..]).unwrap();
+		assert_eq!(svec.split_at(2), (&[1, 2][..], &[3, 4, 5][..]));
+		assert_eq!(svec.split_at(0), (&[][..], &[1, 2, 3, 4, 5][..]));
+		assert_eq!(svec.split_at(5), (&[1, 2, 3, 4, 5][..], &[][..]));
+	}
+
+	#[test_case]
+	fn chunks_even_and_uneven() {
+		let svec: SVec<u8, 8> = SVec::try_from(&[1, 2, 3, 4, 5, 6][..]).unwrap();
+		assert_eq!(svec.chunks(2).count(), 3);
+		assert_eq!(svec.chunks(4).count(), 2);
+		assert_eq!(svec.chunks(4).last(), Some(&[5, 6][..]));
+		let empty: SVec<u8, 8> = SVec::new();
+		assert_eq!(empty.chunks(3).count(), 0);
+	}
+}
@@ -0,0 +1,65 @@
+//! The runner for `#[test_case]` functions.
+//!
+//! Tests are run with `./build.py test`, which builds the kernel with `cargo test` and runs it in QEMU.
+//! Results are reported over the serial port, and QEMU is then exited through its `isa-debug-exit` device,
+//! so the exit code of QEMU tells whether all tests passed.
+
+use x86_64::instructions::port::PortWriteOnly;
+
+/// The exit codes written to the `isa-debug-exit` device.
+///
+/// QEMU exits with `(code << 1) | 1`, so `Success` becomes 33 and `Failed` becomes 35.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum QemuExitCode {
+	Success = 0x10,
+	Failed = 0x11,
+}
+
+/// Exits QEMU with the given exit code.
+///
+/// Requires QEMU to be started with `-device isa-debug-exit,iobase=0xf4,iosize=0x04`.
+/// If it wasn't, this just halts forever.
+pub fn exit_qemu(exit_code: QemuExitCode) -> ! {
+	unsafe {
+		let mut port = PortWriteOnly::<u32>::new(0xF4);
+		port.write(exit_code as u32);
+	}
+	loop {
+		x86_64::instructions::hlt();
+	}
+}
+
+/// Something that can be run as a test.
+///
+/// Implemented for all functions without arguments, which is what `#[test_case]` functions are.
+pub trait Testable {
+	fn run(&self);
+}
+
+impl<T: Fn()> Testable for T {
+	fn run(&self) {
+		serial_print!("{}...\t", core::any::type_name::<T>());
+		self();
+		serial_println!("[ok]");
+	}
+}
+
+/// Runs all tests, and then exits QEMU.
+///
+/// A failing test panics, and the panic handler exits QEMU with `QemuExitCode::Failed`,
+/// so reaching the end of this function means all tests passed.
+pub fn runner(tests: &[&dyn Testable]) {
+	serial_println!("Running {} tests", tests.len());
+	for test in tests {
+		test.run();
+	}
+	exit_qemu(QemuExitCode::Success);
+}
+
+/// Reports the panic of the current test over the serial port, and exits QEMU.
+pub fn panic_handler(info: &core::panic::PanicInfo) -> ! {
+	serial_println!("[failed]");
+	serial_println!("{}", info);
+	exit_qemu(QemuExitCode::Failed);
+}
@@ -0,0 +1,261 @@
+//! A viewer for files which are too big to read into memory.
+//!
+//! Only a window of the file around the visible part is kept in memory, and it is read again
+//! when scrolling moves outside of it, so viewing a file of any size only needs a few KiB.
+//! Long lines are wrapped at the width of the screen. Scrolling up finds the previous row
+//! by looking back for the start of its line, at most [MARGIN] bytes.
+
+use alloc::{string::String, vec::Vec};
+
+use crate::{
+	harddisk::fat32::{self, FatError, FileHandle, Path},
+	printer,
+	ps2_keyboard::{self, KeyCode},
+};
+
+/// How many bytes around the visible part of the file are kept in memory.
+///
+/// Lines longer than this are split into pieces of at most this length when scrolling up through them.
+const MARGIN: usize = 4096;
+
+/// The part of the file which is kept in memory
+struct Window {
+	/// The offset in the file of the first byte of `data`
+	start: usize,
+	data: Vec<u8>,
+}
+
+impl Window {
+	const fn new() -> Self {
+		Self {
+			start: 0,
+			data: Vec::new(),
+		}
+	}
+
+	/// Makes sure the bytes from `from` to `to` in the file are in the window,
+	/// reading the window again, with [MARGIN] bytes on either side, if they aren't.
+	unsafe fn load(&mut self, file: &FileHandle, from: usize, to: usize) -> Result<(), FatError> {
+		let to = to.min(file.len());
+		if from >= self.start && to <= self.start + self.data.len() {
+			return Ok(());
+		}
+
+		self.start = from.saturating_sub(MARGIN);
+		let end = (to + MARGIN).min(file.len());
+		self.data.resize(end - self.start, 0);
+		let mut read = 0;
+		while read < self.data.len() {
+			let count = file.read_at(self.start + read, &mut self.data[read..])?;
+			if count == 0 {
+				break;
+			}
+			read += count;
+		}
+		self.data.truncate(read);
+		Ok(())
+	}
+
+	/// The bytes of the file from `from` to `to`, or as many of them as there are in the window
+	fn get(&self, from: usize, to: usize) -> &[u8] {
+		let end = self.start + self.data.len();
+		&self.data[from.min(end) - self.start..to.min(end) - self.start]
+	}
+}
+
+/// How many bytes of `text` are on the row it starts, including the newline ending the row, if any.
+///
+/// Rows end after a newline, or after `columns` bytes. A newline right after a full row belongs to it,
+/// so lines which are exactly `columns` long aren't followed by an empty row.
+fn row_length(text: &[u8], columns: usize) -> usize {
+	match text.iter().take(columns + 1).position(|&b| b == b'\n') {
+		Some(index) => index + 1,
+		None => text.len().min(columns),
+	}
+}
+
+/// Where the row which ends at the end of `before` starts, as an index into `before`.
+///
+/// `before` has to start at the start of a line, or contain the newline ending the line before that row.
+fn previous_row_start(before: &[u8], columns: usize) -> usize {
+	if before.is_empty() {
+		return 0;
+	}
+	// The last byte may be the newline ending the row, which doesn't start a new line
+	let line_start = before[..before.len() - 1]
+		.iter()
+		.rposition(|&b| b == b'\n')
+		.map_or(0, |index| index + 1);
+	let mut row_start = line_start;
+	loop {
+		let next_row_start = row_start + row_length(&before[row_start..], columns);
+		if next_row_start >= before.len() {
+			return row_start;
+		}
+		row_start = next_row_start;
+	}
+}
+
+/// Replaces everything but printable ASCII with `.`, and drops newlines
+fn printable_row(text: &[u8]) -> String {
+	text
+		.iter()
+		.filter(|&&b| b != b'\n' && b != b'\r')
+		.map(|&b| match b {
+			b' '..=b'~' => b as char,
+			b'\t' => ' ',
+			_ => '.',
+		})
+		.collect()
+}
+
+/// Shows the file at `path` one screen at a time, until Escape or Q is pressed.
+///
+/// Up and Down scroll one row, and Page Up and Page Down (or Space) one screen.
+pub unsafe fn view(path: Path) -> Result<(), FatError> {
+	let file = fat32::open(path)?;
+	let (columns, rows) = printer::text_size();
+	// The last column is kept free, as the printer wraps when it is written to,
+	// and the last row is used for the status line
+	let (columns, rows) = (columns - 1, rows - 1);
+	let mut window = Window::new();
+	let mut top = 0;
+
+	loop {
+		window.load(&file, top, top + (columns + 1) * rows)?;
+		draw(&window, path, file.len(), top, columns, rows);
+
+		let event = ps2_keyboard::get_key_event();
+		let (down, count) = match (event.keycode, event.char) {
+			(KeyCode::Escape, _) | (_, Some('q')) | (_, Some('Q')) => break,
+			(KeyCode::Down, _) => (true, 1),
+			(KeyCode::Up, _) => (false, 1),
+			(KeyCode::PageDown, _) | (_, Some(' ')) => (true, rows),
+			(KeyCode::PageUp, _) => (false, rows),
+			_ => continue,
+		};
+
+		for _ in 0..count {
+			if down {
+				window.load(&file, top, top + columns + 1)?;
+				let next_row_start = top + row_length(window.get(top, top + columns + 1), columns);
+				// The last row stays on screen
+				if next_row_start >= file.len() {
+					break;
+				}
+				top = next_row_start;
+			} else {
+				if top == 0 {
+					break;
+				}
+				let from = top.saturating_sub(MARGIN);
+				window.load(&file, from, top)?;
+				let before = window.get(from, top);
+				top = from + previous_row_start(before, columns);
+			}
+		}
+	}
+
+	println!();
+	file.close()
+}
+
+/// Draws `rows` rows of the file starting at byte `top`, and a status line below them
+unsafe fn draw(
+	window: &Window,
+	path: Path,
+	file_len: usize,
+	top: usize,
+	columns: usize,
+	rows: usize,
+) {
+	if printer::is_initialized() {
+		printer::clear();
+	}
+	let mut row_start = top;
+	for _ in 0..rows {
+		let text = window.get(row_start, row_start + columns + 1);
+		let length = row_length(text, columns);
+		println!("{}", printable_row(&text[..length]));
+		row_start += length;
+	}
+	// No newline, as it would scroll the first row off the screen
+	print!(
+		"{}  {}-{} of {} bytes  (arrows scroll, q quits)",
+		String::from_utf8_lossy(path),
+		top,
+		row_start,
+		file_len
+	);
+}
+
+#[cfg(test)]
+mod tests {
+	use alloc::vec::Vec;
+
+	use super::{previous_row_start, printable_row, row_length, Window, MARGIN};
+	use crate::harddisk::fat32;
+
+	#[test_case]
+	fn rows_end_at_newlines_or_width() {
+		assert_eq!(row_length(b"abc\ndef", 10), 4);
+		assert_eq!(row_length(b"abcdefghijkl", 5), 5);
+		assert_eq!(row_length(b"abc", 5), 3);
+		assert_eq!(row_length(b"\n\n", 5), 1);
+		// A newline right after a full row belongs to it
+		assert_eq!(row_length(b"abcde\nf", 5), 6);
+		assert_eq!(row_length(b"", 5), 0);
+	}
+
+	#[test_case]
+	fn scrolling_up_undoes_scrolling_down() {
+		let text = b"short\na line which is long enough to wrap twice\n\nexactly10!\nend";
+		let columns = 10;
+		let mut row_starts = Vec::new();
+		let mut row_start = 0;
+		while row_start < text.len() {
+			row_starts.push(row_start);
+			row_start += row_length(&text[row_start..], columns);
+		}
+		assert_eq!(row_starts, [0, 6, 16, 26, 36, 46, 48, 49, 60]);
+
+		for window in row_starts.windows(2) {
+			assert_eq!(previous_row_start(&text[..window[1]], columns), window[0]);
+		}
+		assert_eq!(previous_row_start(b"", columns), 0);
+	}
+
+	#[test_case]
+	fn unprintable_bytes_are_replaced() {
+		assert_eq!(printable_row(b"ok\tthen\r\n"), "ok then");
+		assert_eq!(printable_row(&[b'a', 0, 0xC3, 0xA5, b'b']), "a...b");
+	}
+
+	#[test_case]
+	fn only_a_window_of_the_file_is_loaded() {
+		unsafe {
+			let data: Vec<u8> = (0..MARGIN * 5).map(|i| (i % 251) as u8).collect();
+			fat32::write_file(b"EFI>VIEWED", &data).unwrap();
+			let file = fat32::open(b"EFI>VIEWED").unwrap();
+
+			let mut window = Window::new();
+			let from = MARGIN * 2 + 100;
+			window.load(&file, from, from + 500).unwrap();
+			assert_eq!(window.get(from, from + 500), &data[from..from + 500]);
+			assert!(window.data.len() <= 500 + 2 * MARGIN);
+
+			// Inside the window, nothing is read again
+			let start = window.start;
+			window.load(&file, from + 10, from + 20).unwrap();
+			assert_eq!(window.start, start);
+
+			// The end of the file cuts the window short
+			let from = data.len() - 10;
+			window.load(&file, from, from + 500).unwrap();
+			assert_eq!(window.get(from, from + 500), &data[from..]);
+
+			file.close().unwrap();
+			fat32::delete_file(b"EFI>VIEWED").unwrap();
+		}
+	}
+}